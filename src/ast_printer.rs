@@ -19,20 +19,51 @@ impl AstPrinter {
 
     pub fn print(&self, expr: &Expr) -> String {
         match expr {
-            Expr::Unary { operator, right } => self.parenthesize(&operator.lexeme, &[right]),
+            Expr::Unary {
+                operator, right, ..
+            } => self.parenthesize(&operator.lexeme, &[right]),
             Expr::Binary {
                 left,
                 operator,
                 right,
+                ..
             } => self.parenthesize(&operator.lexeme, &[left, right]),
-            Expr::Grouping { expression } => self.parenthesize("group", &[expression]),
-            Expr::Literal { value } => value.to_string(),
+            Expr::Grouping { expression, .. } => self.parenthesize("group", &[expression]),
+            Expr::Literal { value, .. } => value.to_string(),
             Expr::Ternary {
                 condition,
                 positive,
                 negative,
+                ..
             } => self.parenthesize("?:", &[condition, positive, negative]),
-            Expr::Variable { name } => name.to_string(),
+            Expr::Logical {
+                left,
+                operator,
+                right,
+                ..
+            } => self.parenthesize(&operator.lexeme, &[left, right]),
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                let mut exprs: Vec<&Expr> = vec![callee];
+                exprs.extend(arguments.iter());
+                self.parenthesize("call", &exprs)
+            }
+            Expr::Get { object, name, .. } => {
+                self.parenthesize(&format!(". {}", name.lexeme), &[object])
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+                ..
+            } => self.parenthesize(&format!("= .{}", name.lexeme), &[object, value]),
+            Expr::This { keyword, .. } => keyword.lexeme.clone(),
+            Expr::Super { method, .. } => format!("(super {})", method.lexeme),
+            Expr::Variable { name, .. } => name.to_string(),
+            Expr::Assign { name, value, .. } => {
+                self.parenthesize(&format!("= {}", name.lexeme), &[value])
+            }
         }
     }
 }
@@ -1,7 +1,7 @@
 use rlox::{
     ast_printer::AstPrinter,
     expr::Expr,
-    token::{Literal, Token, TokenType},
+    token::{Literal, Span, Token, TokenType},
 };
 
 fn main() {
@@ -10,14 +10,19 @@ fn main() {
             operator: Token::new(TokenType::Minus, "-".to_string(), None, 1),
             right: Box::new(Expr::Literal {
                 value: Literal::Number(123.0),
+                span: Span::default(),
             }),
+            span: Span::default(),
         }),
         operator: Token::new(TokenType::Star, "*".to_string(), None, 1),
         right: Box::new(Expr::Grouping {
             expression: Box::new(Expr::Literal {
                 value: Literal::Number(45.67),
+                span: Span::default(),
             }),
+            span: Span::default(),
         }),
+        span: Span::default(),
     };
 
     let ast_printer = AstPrinter;
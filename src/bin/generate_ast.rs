@@ -4,24 +4,61 @@ use std::io::Write;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let expressions = HashMap::from([
+        (
+            "Assign",
+            "name: Token, value: Box<Expr>, id: ExprId, span: Span",
+        ),
         (
             "Binary",
-            "left: Box<Expr>, operator: Token, right: Box<Expr>",
+            "left: Box<Expr>, operator: Token, right: Box<Expr>, span: Span",
         ),
-        ("Grouping", "expression: Box<Expr>"),
-        ("Literal", "value: Literal"),
-        ("Unary", "operator: Token, right: Box<Expr>"),
+        ("Grouping", "expression: Box<Expr>, span: Span"),
+        ("Literal", "value: Literal, span: Span"),
+        ("Unary", "operator: Token, right: Box<Expr>, span: Span"),
         (
             "Ternary",
-            "condition: Box<Expr>, positive: Box<Expr>, negative: Box<Expr>",
+            "condition: Box<Expr>, positive: Box<Expr>, negative: Box<Expr>, span: Span",
+        ),
+        ("Variable", "name: Token, id: ExprId, span: Span"),
+        ("Get", "object: Box<Expr>, name: Token, span: Span"),
+        (
+            "Set",
+            "object: Box<Expr>, name: Token, value: Box<Expr>, span: Span",
+        ),
+        ("This", "keyword: Token, id: ExprId, span: Span"),
+        (
+            "Super",
+            "keyword: Token, method: Token, id: ExprId, span: Span",
+        ),
+        (
+            "Call",
+            "callee: Box<Expr>, paren: Token, arguments: Vec<Expr>, span: Span",
+        ),
+        (
+            "Logical",
+            "left: Box<Expr>, operator: Token, right: Box<Expr>, span: Span",
         ),
-        ("Variable", "name: Token"),
     ]);
 
     let statements = HashMap::from([
-        ("Expression", "expression: Box<Expr>"),
-        ("Print", "expression: Box<Expr>"),
-        ("Var", "name: Token, initializer: Box<Expr>"),
+        ("Expression", "expression: Box<Expr>, span: Span"),
+        ("Print", "expression: Box<Expr>, span: Span"),
+        ("Var", "name: Token, initializer: Box<Expr>, span: Span"),
+        ("Block", "statements: Vec<Stmt>, span: Span"),
+        (
+            "If",
+            "condition: Box<Expr>, then_branch: Box<Stmt>, else_branch: Option<Box<Stmt>>, span: Span",
+        ),
+        ("While", "condition: Box<Expr>, body: Box<Stmt>, span: Span"),
+        ("Function", "declaration: Rc<FunctionDecl>, span: Span"),
+        (
+            "Return",
+            "keyword: Token, value: Option<Box<Expr>>, span: Span",
+        ),
+        (
+            "Class",
+            "name: Token, superclass: Option<Box<Expr>>, methods: Vec<Rc<FunctionDecl>>, span: Span",
+        ),
     ]);
 
     define_ast("Expr", expressions, &["crate::token::*"])?;
@@ -30,7 +67,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     define_ast(
         "Stmt",
         statements,
-        &["crate::expr::Expr", "crate::token::Token"],
+        &[
+            "std::rc::Rc",
+            "crate::expr::Expr",
+            "crate::token::{Span, Token}",
+        ],
     )?;
     println!("Generated Stmt enum");
 
@@ -0,0 +1,203 @@
+use std::{
+    fmt::{self, Display},
+    rc::Rc,
+};
+
+use crate::token::Span;
+
+/// A single bytecode instruction. Operands follow the opcode in the code
+/// stream; multi-byte operands are big-endian `u16`s. The numbering is part of
+/// the `.rloxc` format, so changing it means bumping its version.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OpCode {
+    /// `constant: u16`. Pushes a number or string constant.
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    /// `slot: u16`.
+    GetLocal,
+    /// `slot: u16`.
+    SetLocal,
+    /// `name: u16`.
+    GetGlobal,
+    /// `name: u16`.
+    DefineGlobal,
+    /// `name: u16`.
+    SetGlobal,
+    /// `index: u16`.
+    GetUpvalue,
+    /// `index: u16`.
+    SetUpvalue,
+    /// `name: u16`.
+    GetProperty,
+    /// `name: u16`.
+    SetProperty,
+    /// `name: u16`. Pops the superclass and `this`, pushes the bound method.
+    GetSuper,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Comma,
+    Not,
+    Negate,
+    Print,
+    /// `offset: u16`, forwards.
+    Jump,
+    /// `offset: u16`, forwards. Leaves the condition on the stack.
+    JumpIfFalse,
+    /// `offset: u16`, backwards.
+    Loop,
+    /// `arguments: u8`.
+    Call,
+    /// `function: u16`, then `is_local: u8, index: u16` per captured variable.
+    Closure,
+    CloseUpvalue,
+    Return,
+    /// `name: u16, methods: u16, has_superclass: u8`. Pops the method
+    /// closures and pushes the new class. The superclass, if any, is the
+    /// value just below the methods.
+    Class,
+}
+
+impl OpCode {
+    const ALL: [OpCode; 37] = [
+        OpCode::Constant,
+        OpCode::Nil,
+        OpCode::True,
+        OpCode::False,
+        OpCode::Pop,
+        OpCode::GetLocal,
+        OpCode::SetLocal,
+        OpCode::GetGlobal,
+        OpCode::DefineGlobal,
+        OpCode::SetGlobal,
+        OpCode::GetUpvalue,
+        OpCode::SetUpvalue,
+        OpCode::GetProperty,
+        OpCode::SetProperty,
+        OpCode::GetSuper,
+        OpCode::Equal,
+        OpCode::NotEqual,
+        OpCode::Greater,
+        OpCode::GreaterEqual,
+        OpCode::Less,
+        OpCode::LessEqual,
+        OpCode::Add,
+        OpCode::Subtract,
+        OpCode::Multiply,
+        OpCode::Divide,
+        OpCode::Comma,
+        OpCode::Not,
+        OpCode::Negate,
+        OpCode::Print,
+        OpCode::Jump,
+        OpCode::JumpIfFalse,
+        OpCode::Loop,
+        OpCode::Call,
+        OpCode::Closure,
+        OpCode::CloseUpvalue,
+        OpCode::Return,
+        OpCode::Class,
+    ];
+}
+
+impl TryFrom<u8> for OpCode {
+    type Error = u8;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
+        OpCode::ALL.get(byte as usize).copied().ok_or(byte)
+    }
+}
+
+/// An entry in a value pool shared by the instructions of one chunk.
+#[derive(Clone, Debug)]
+pub enum Constant {
+    Number(f64),
+    String(Rc<str>),
+    Function(Rc<FunctionProto>),
+}
+
+impl Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Constant::Number(number) => write!(f, "{}", number),
+            Constant::String(string) => write!(f, "{}", string),
+            Constant::Function(function) => write!(f, "<fn {}>", function.name),
+        }
+    }
+}
+
+/// A compiled function body: everything needed to create closures of it.
+#[derive(Debug)]
+pub struct FunctionProto {
+    pub name: String,
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+}
+
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Constant>,
+    /// Source span of the instructions from each offset onwards, recorded
+    /// only where the span changes.
+    pub spans: Vec<(usize, Span)>,
+}
+
+impl Chunk {
+    pub fn write(&mut self, byte: u8, span: Span) {
+        if self.spans.last().is_none_or(|(_, last)| *last != span) {
+            self.spans.push((self.code.len(), span));
+        }
+
+        self.code.push(byte);
+    }
+
+    pub fn write_u16(&mut self, value: u16, span: Span) {
+        for byte in value.to_be_bytes() {
+            self.write(byte, span);
+        }
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+    }
+
+    /// Adds `constant` to the pool, reusing an equal number or string
+    /// already there. Returns its index.
+    pub fn add_constant(&mut self, constant: Constant) -> usize {
+        let existing = self
+            .constants
+            .iter()
+            .position(|other| match (other, &constant) {
+                (Constant::Number(a), Constant::Number(b)) => a.to_bits() == b.to_bits(),
+                (Constant::String(a), Constant::String(b)) => a == b,
+                _ => false,
+            });
+
+        existing.unwrap_or_else(|| {
+            self.constants.push(constant);
+            self.constants.len() - 1
+        })
+    }
+
+    /// The source span of the instruction at `offset`.
+    pub fn span_at(&self, offset: usize) -> Span {
+        let index = self.spans.partition_point(|(start, _)| *start <= offset);
+        index
+            .checked_sub(1)
+            .map(|index| self.spans[index].1)
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,87 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    rc::Rc,
+};
+
+use crate::{
+    function::LoxFunction,
+    heap::Trace,
+    instance::LoxInstance,
+    interpreter::{Interpreter, RuntimeError},
+    token::Token,
+    value::Value,
+};
+
+pub struct LoxClass {
+    pub name: String,
+    superclass: Option<Rc<LoxClass>>,
+    methods: HashMap<String, Rc<LoxFunction>>,
+}
+
+impl LoxClass {
+    pub(crate) fn new(
+        name: String,
+        superclass: Option<Rc<LoxClass>>,
+        methods: HashMap<String, Rc<LoxFunction>>,
+    ) -> Self {
+        LoxClass {
+            name,
+            superclass,
+            methods,
+        }
+    }
+
+    pub fn find_method(&self, name: &str) -> Option<Rc<LoxFunction>> {
+        if let Some(method) = self.methods.get(name) {
+            return Some(Rc::clone(method));
+        }
+
+        self.superclass
+            .as_ref()
+            .and_then(|superclass| superclass.find_method(name))
+    }
+
+    pub fn arity(&self) -> usize {
+        self.find_method("init")
+            .map(|initializer| initializer.arity())
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn call(
+        self: &Rc<Self>,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Value>,
+        paren: &Token,
+    ) -> Result<Value, RuntimeError> {
+        let instance = interpreter
+            .heap_mut()
+            .instance(LoxInstance::new(Rc::clone(self)));
+
+        if let Some(initializer) = self.find_method("init") {
+            initializer
+                .bind(Rc::clone(&instance), interpreter.heap_mut())
+                .call(interpreter, arguments, paren)?;
+        }
+
+        Ok(Value::Instance(instance))
+    }
+}
+
+impl Trace for LoxClass {
+    fn trace(&self, visit: &mut dyn FnMut(*const ())) {
+        if let Some(superclass) = &self.superclass {
+            visit(Rc::as_ptr(superclass) as *const ());
+        }
+
+        for method in self.methods.values() {
+            visit(Rc::as_ptr(method) as *const ());
+        }
+    }
+}
+
+impl Display for LoxClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
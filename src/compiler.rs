@@ -0,0 +1,635 @@
+use std::{error::Error, fmt::Display, rc::Rc};
+
+use crate::{
+    chunk::{Chunk, Constant, FunctionProto, OpCode},
+    expr::Expr,
+    stmt::{FunctionDecl, Stmt},
+    token::{Literal, Span, Token, TokenType},
+};
+
+#[derive(Clone, Debug)]
+pub struct CompileError {
+    pub code: &'static str,
+    pub line: usize,
+    pub message: String,
+    pub span: Span,
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[line {}:{}] {}",
+            self.line, self.span.column, self.message
+        )
+    }
+}
+
+impl Error for CompileError {}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+    Script,
+    Function,
+    Initializer,
+    Method,
+}
+
+struct Local {
+    name: String,
+    depth: usize,
+    is_captured: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Upvalue {
+    index: u16,
+    is_local: bool,
+}
+
+/// Compilation state of one function. Slot zero holds the callee, or the
+/// receiver in methods, where it is named `this`.
+struct FunctionState {
+    name: String,
+    function_type: FunctionType,
+    chunk: Chunk,
+    locals: Vec<Local>,
+    upvalues: Vec<Upvalue>,
+    scope_depth: usize,
+}
+
+impl FunctionState {
+    fn new(name: String, function_type: FunctionType) -> Self {
+        let receiver = match function_type {
+            FunctionType::Method | FunctionType::Initializer => "this",
+            FunctionType::Script | FunctionType::Function => "",
+        };
+
+        FunctionState {
+            name,
+            function_type,
+            chunk: Chunk::default(),
+            locals: vec![Local {
+                name: receiver.to_string(),
+                depth: 0,
+                is_captured: false,
+            }],
+            upvalues: vec![],
+            scope_depth: 0,
+        }
+    }
+}
+
+/// Compiles resolved statements into bytecode for the VM. Scoping errors have
+/// already been reported by the resolver, so the only failures left are
+/// exceeding the limits of the bytecode format.
+pub struct Compiler {
+    functions: Vec<FunctionState>,
+    errors: Vec<CompileError>,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Compiler {
+            functions: vec![FunctionState::new(
+                "script".to_string(),
+                FunctionType::Script,
+            )],
+            errors: vec![],
+        }
+    }
+
+    pub fn compile(mut self, statements: &[Stmt]) -> Result<Rc<FunctionProto>, Vec<CompileError>> {
+        for statement in statements {
+            self.statement(statement);
+        }
+
+        let span = statements.last().map(Stmt::span).unwrap_or_default();
+        self.emit_return(span);
+
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+
+        let script = self.functions.pop().expect("the script is always compiled");
+        Ok(Rc::new(FunctionProto {
+            name: script.name,
+            arity: 0,
+            upvalue_count: 0,
+            chunk: script.chunk,
+        }))
+    }
+
+    fn report_error(&mut self, code: &'static str, message: &str, span: Span) {
+        self.errors.push(CompileError {
+            code,
+            line: span.line,
+            message: message.to_string(),
+            span,
+        });
+    }
+
+    fn current(&mut self) -> &mut FunctionState {
+        self.functions
+            .last_mut()
+            .expect("a function is always being compiled")
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.current().chunk
+    }
+
+    fn emit(&mut self, op: OpCode, span: Span) {
+        self.chunk().write(op as u8, span);
+    }
+
+    fn emit_with(&mut self, op: OpCode, operand: u16, span: Span) {
+        self.emit(op, span);
+        self.chunk().write_u16(operand, span);
+    }
+
+    fn emit_return(&mut self, span: Span) {
+        if self.current().function_type == FunctionType::Initializer {
+            self.emit_with(OpCode::GetLocal, 0, span);
+        } else {
+            self.emit(OpCode::Nil, span);
+        }
+        self.emit(OpCode::Return, span);
+    }
+
+    fn make_constant(&mut self, constant: Constant, span: Span) -> u16 {
+        let index = self.chunk().add_constant(constant);
+        u16::try_from(index).unwrap_or_else(|_| {
+            self.report_error("C0001", "Too many constants in one chunk.", span);
+            0
+        })
+    }
+
+    fn identifier_constant(&mut self, name: &Token) -> u16 {
+        self.make_constant(Constant::String(name.lexeme.as_str().into()), name.span)
+    }
+
+    /// Emits a forward jump with a placeholder offset, returning the offset
+    /// of the placeholder for [`Compiler::patch_jump`].
+    fn emit_jump(&mut self, op: OpCode, span: Span) -> usize {
+        self.emit_with(op, u16::MAX, span);
+        self.chunk().code.len() - 2
+    }
+
+    fn patch_jump(&mut self, placeholder: usize, span: Span) {
+        let distance = self.chunk().code.len() - placeholder - 2;
+        let Ok(distance) = u16::try_from(distance) else {
+            self.report_error("C0003", "Too much code to jump over.", span);
+            return;
+        };
+
+        let [high, low] = distance.to_be_bytes();
+        self.chunk().code[placeholder] = high;
+        self.chunk().code[placeholder + 1] = low;
+    }
+
+    fn emit_loop(&mut self, loop_start: usize, span: Span) {
+        self.emit(OpCode::Loop, span);
+        let distance = self.chunk().code.len() - loop_start + 2;
+        let distance = u16::try_from(distance).unwrap_or_else(|_| {
+            self.report_error("C0003", "Too much code to jump over.", span);
+            0
+        });
+        self.chunk().write_u16(distance, span);
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self, span: Span) {
+        let function = self.current();
+        function.scope_depth -= 1;
+
+        while let Some(local) = function
+            .locals
+            .pop_if(|local| local.depth > function.scope_depth)
+        {
+            let op = if local.is_captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            };
+            function.chunk.write(op as u8, span);
+        }
+    }
+
+    fn add_local(&mut self, name: &str, span: Span) {
+        let function = self.current();
+        if function.locals.len() > u16::MAX as usize {
+            self.report_error("C0002", "Too many local variables in function.", span);
+            return;
+        }
+
+        let depth = function.scope_depth;
+        function.locals.push(Local {
+            name: name.to_string(),
+            depth,
+            is_captured: false,
+        });
+    }
+
+    /// Binds the value on top of the stack to `name`: a new local slot inside
+    /// a scope, or a global at the top level.
+    fn define_variable(&mut self, name: &Token) {
+        if self.current().scope_depth > 0 {
+            self.add_local(&name.lexeme, name.span);
+            return;
+        }
+
+        let constant = self.identifier_constant(name);
+        self.emit_with(OpCode::DefineGlobal, constant, name.span);
+    }
+
+    fn resolve_local(&self, function: usize, name: &str) -> Option<u16> {
+        self.functions[function]
+            .locals
+            .iter()
+            .rposition(|local| local.name == name)
+            .map(|slot| slot as u16)
+    }
+
+    fn resolve_upvalue(&mut self, function: usize, name: &str, span: Span) -> Option<u16> {
+        let enclosing = function.checked_sub(1)?;
+
+        if let Some(slot) = self.resolve_local(enclosing, name) {
+            self.functions[enclosing].locals[slot as usize].is_captured = true;
+            return Some(self.add_upvalue(function, slot, true, span));
+        }
+
+        let index = self.resolve_upvalue(enclosing, name, span)?;
+        Some(self.add_upvalue(function, index, false, span))
+    }
+
+    fn add_upvalue(&mut self, function: usize, index: u16, is_local: bool, span: Span) -> u16 {
+        let upvalue = Upvalue { index, is_local };
+        let upvalues = &mut self.functions[function].upvalues;
+
+        if let Some(existing) = upvalues.iter().position(|other| *other == upvalue) {
+            return existing as u16;
+        }
+
+        if upvalues.len() > u16::MAX as usize {
+            self.report_error("C0004", "Too many closure variables in function.", span);
+            return 0;
+        }
+
+        upvalues.push(upvalue);
+        (upvalues.len() - 1) as u16
+    }
+
+    fn named_variable(&mut self, name: &str, span: Span, assign: bool) {
+        let current = self.functions.len() - 1;
+
+        let (op, operand) = if let Some(slot) = self.resolve_local(current, name) {
+            (OpCode::GetLocal, slot)
+        } else if let Some(index) = self.resolve_upvalue(current, name, span) {
+            (OpCode::GetUpvalue, index)
+        } else {
+            let constant = self.make_constant(Constant::String(name.into()), span);
+            (OpCode::GetGlobal, constant)
+        };
+
+        let op = match (op, assign) {
+            (op, false) => op,
+            (OpCode::GetLocal, true) => OpCode::SetLocal,
+            (OpCode::GetUpvalue, true) => OpCode::SetUpvalue,
+            (_, true) => OpCode::SetGlobal,
+        };
+
+        self.emit_with(op, operand, span);
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression { expression, span } => {
+                self.expression(expression);
+                self.emit(OpCode::Pop, *span);
+            }
+            Stmt::Print { expression, span } => {
+                self.expression(expression);
+                self.emit(OpCode::Print, *span);
+            }
+            Stmt::Var {
+                name, initializer, ..
+            } => {
+                self.expression(initializer);
+                self.define_variable(name);
+            }
+            Stmt::Block { statements, span } => {
+                self.begin_scope();
+                for statement in statements {
+                    self.statement(statement);
+                }
+                self.end_scope(*span);
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                span,
+            } => {
+                self.expression(condition);
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse, *span);
+                self.emit(OpCode::Pop, *span);
+                self.statement(then_branch);
+
+                let else_jump = self.emit_jump(OpCode::Jump, *span);
+                self.patch_jump(then_jump, *span);
+                self.emit(OpCode::Pop, *span);
+
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+                self.patch_jump(else_jump, *span);
+            }
+            Stmt::While {
+                condition,
+                body,
+                span,
+            } => {
+                let loop_start = self.chunk().code.len();
+                self.expression(condition);
+
+                let exit_jump = self.emit_jump(OpCode::JumpIfFalse, *span);
+                self.emit(OpCode::Pop, *span);
+                self.statement(body);
+                self.emit_loop(loop_start, *span);
+
+                self.patch_jump(exit_jump, *span);
+                self.emit(OpCode::Pop, *span);
+            }
+            Stmt::Function { declaration, .. } => {
+                // Declared before the body is compiled so it can call itself.
+                if self.current().scope_depth > 0 {
+                    self.add_local(&declaration.name.lexeme, declaration.name.span);
+                    self.function(declaration, FunctionType::Function);
+                } else {
+                    self.function(declaration, FunctionType::Function);
+                    self.define_variable(&declaration.name);
+                }
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+                span,
+            } => self.class(name, superclass.as_deref(), methods, *span),
+            Stmt::Return { value, span, .. } => {
+                match value {
+                    Some(value) => {
+                        self.expression(value);
+                        self.emit(OpCode::Return, *span);
+                    }
+                    None => self.emit_return(*span),
+                };
+            }
+        }
+    }
+
+    fn function(&mut self, declaration: &FunctionDecl, function_type: FunctionType) {
+        let span = declaration.name.span;
+
+        self.functions.push(FunctionState::new(
+            declaration.name.lexeme.clone(),
+            function_type,
+        ));
+        self.begin_scope();
+
+        for param in &declaration.params {
+            self.add_local(&param.lexeme, param.span);
+        }
+        for statement in &declaration.body {
+            self.statement(statement);
+        }
+        self.emit_return(span);
+
+        let function = self.functions.pop().expect("the function was just pushed");
+        let proto = FunctionProto {
+            name: function.name,
+            arity: declaration.params.len(),
+            upvalue_count: function.upvalues.len(),
+            chunk: function.chunk,
+        };
+
+        let constant = self.make_constant(Constant::Function(Rc::new(proto)), span);
+        self.emit_with(OpCode::Closure, constant, span);
+
+        for upvalue in function.upvalues {
+            self.chunk().write(upvalue.is_local as u8, span);
+            self.chunk().write_u16(upvalue.index, span);
+        }
+    }
+
+    fn class(
+        &mut self,
+        name: &Token,
+        superclass: Option<&Expr>,
+        methods: &[Rc<FunctionDecl>],
+        span: Span,
+    ) {
+        // A local class gets its slot up front so methods can refer to it.
+        let is_local = self.current().scope_depth > 0;
+        if is_local {
+            self.emit(OpCode::Nil, name.span);
+            self.add_local(&name.lexeme, name.span);
+        }
+
+        // Methods of a subclass close over a scope holding `super`.
+        let mut superclass_span = name.span;
+        if let Some(superclass) = superclass {
+            if let Expr::Variable { name, .. } = superclass {
+                superclass_span = name.span;
+            }
+
+            self.expression(superclass);
+            self.begin_scope();
+            self.add_local("super", superclass_span);
+        }
+
+        for method in methods {
+            let function_type = if method.name.lexeme == "init" {
+                FunctionType::Initializer
+            } else {
+                FunctionType::Method
+            };
+            self.function(method, function_type);
+        }
+
+        let method_count = u16::try_from(methods.len()).unwrap_or_else(|_| {
+            self.report_error("C0005", "Too many methods in class.", span);
+            0
+        });
+
+        let constant = self.identifier_constant(name);
+        self.emit_with(OpCode::Class, constant, superclass_span);
+        self.chunk().write_u16(method_count, superclass_span);
+        self.chunk()
+            .write(superclass.is_some() as u8, superclass_span);
+
+        if is_local {
+            let current = self.functions.len() - 1;
+            let slot = self
+                .resolve_local(current, &name.lexeme)
+                .expect("the class slot was just added");
+            self.emit_with(OpCode::SetLocal, slot, name.span);
+            self.emit(OpCode::Pop, name.span);
+        } else {
+            let constant = self.identifier_constant(name);
+            self.emit_with(OpCode::DefineGlobal, constant, name.span);
+        }
+
+        if superclass.is_some() {
+            self.end_scope(span);
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal { value, span } => match value {
+                Literal::Nil => self.emit(OpCode::Nil, *span),
+                Literal::Boolean(true) => self.emit(OpCode::True, *span),
+                Literal::Boolean(false) => self.emit(OpCode::False, *span),
+                Literal::Number(number) => {
+                    let constant = self.make_constant(Constant::Number(*number), *span);
+                    self.emit_with(OpCode::Constant, constant, *span);
+                }
+                Literal::String(string) => {
+                    let constant =
+                        self.make_constant(Constant::String(string.as_str().into()), *span);
+                    self.emit_with(OpCode::Constant, constant, *span);
+                }
+            },
+            Expr::Grouping { expression, .. } => self.expression(expression),
+            Expr::Unary {
+                operator, right, ..
+            } => {
+                self.expression(right);
+                let op = match operator.token_type {
+                    TokenType::Minus => OpCode::Negate,
+                    _ => OpCode::Not,
+                };
+                self.emit(op, operator.span);
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+                ..
+            } => {
+                self.expression(left);
+                self.expression(right);
+
+                let op = match operator.token_type {
+                    TokenType::EqualEqual => OpCode::Equal,
+                    TokenType::BangEqual => OpCode::NotEqual,
+                    TokenType::Greater => OpCode::Greater,
+                    TokenType::GreaterEqual => OpCode::GreaterEqual,
+                    TokenType::Less => OpCode::Less,
+                    TokenType::LessEqual => OpCode::LessEqual,
+                    TokenType::Plus => OpCode::Add,
+                    TokenType::Minus => OpCode::Subtract,
+                    TokenType::Star => OpCode::Multiply,
+                    TokenType::Slash => OpCode::Divide,
+                    _ => OpCode::Comma,
+                };
+                self.emit(op, operator.span);
+            }
+            Expr::Ternary {
+                condition,
+                positive,
+                negative,
+                span,
+            } => {
+                self.expression(condition);
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse, *span);
+                self.emit(OpCode::Pop, *span);
+                self.expression(positive);
+
+                let end_jump = self.emit_jump(OpCode::Jump, *span);
+                self.patch_jump(else_jump, *span);
+                self.emit(OpCode::Pop, *span);
+                self.expression(negative);
+                self.patch_jump(end_jump, *span);
+            }
+            Expr::Logical {
+                left,
+                operator,
+                right,
+                span,
+            } => {
+                self.expression(left);
+
+                if operator.token_type == TokenType::Or {
+                    let else_jump = self.emit_jump(OpCode::JumpIfFalse, *span);
+                    let end_jump = self.emit_jump(OpCode::Jump, *span);
+                    self.patch_jump(else_jump, *span);
+                    self.emit(OpCode::Pop, *span);
+                    self.expression(right);
+                    self.patch_jump(end_jump, *span);
+                } else {
+                    let end_jump = self.emit_jump(OpCode::JumpIfFalse, *span);
+                    self.emit(OpCode::Pop, *span);
+                    self.expression(right);
+                    self.patch_jump(end_jump, *span);
+                }
+            }
+            Expr::Variable { name, .. } => self.named_variable(&name.lexeme, name.span, false),
+            Expr::Assign { name, value, .. } => {
+                self.expression(value);
+                self.named_variable(&name.lexeme, name.span, true);
+            }
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+                ..
+            } => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+
+                // The parser caps argument lists at 255.
+                self.emit(OpCode::Call, paren.span);
+                self.chunk().write(arguments.len() as u8, paren.span);
+            }
+            Expr::Get { object, name, .. } => {
+                self.expression(object);
+                let constant = self.identifier_constant(name);
+                self.emit_with(OpCode::GetProperty, constant, name.span);
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+                ..
+            } => {
+                self.expression(object);
+                self.expression(value);
+                let constant = self.identifier_constant(name);
+                self.emit_with(OpCode::SetProperty, constant, name.span);
+            }
+            Expr::This { keyword, .. } => self.named_variable("this", keyword.span, false),
+            Expr::Super {
+                keyword, method, ..
+            } => {
+                self.named_variable("this", keyword.span, false);
+                self.named_variable("super", keyword.span, false);
+                let constant = self.identifier_constant(method);
+                self.emit_with(OpCode::GetSuper, constant, method.span);
+            }
+        }
+    }
+}
@@ -0,0 +1,691 @@
+//! Conversions between Lox values and Rust types through serde, enabled by
+//! the `serde` feature.
+//!
+//! Strings, numbers, booleans and `nil` map to their serde counterparts.
+//! Instances map to structs and maps, one entry per field; instances made
+//! from Rust values belong to a class named after the struct, or `Object`
+//! for maps. Unit enum variants map to strings. Lox has no lists, so
+//! sequences and tuples cannot be converted, and neither can functions,
+//! classes or instances that contain themselves.
+//!
+//! Only an instance's fields are converted, not its class. An instance that
+//! goes out to Rust and back, even as a [`Value`], comes back as an `Object`.
+//!
+//! ```
+//! use rlox::{Backend, Rlox};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize, Debug, PartialEq)]
+//! struct Config {
+//!     name: String,
+//!     retries: u32,
+//! }
+//!
+//! let mut rlox = Rlox::with_backend(Backend::Vm);
+//! let config = Config { name: "lox".to_string(), retries: 3 };
+//! rlox.set_global("config", rlox::to_value(&config).unwrap());
+//!
+//! let value = rlox.eval("config.retries = config.retries + 1; config;").unwrap();
+//! let config: Config = rlox::from_value(value).unwrap();
+//! assert_eq!(config, Config { name: "lox".to_string(), retries: 4 });
+//! ```
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt::{self, Display},
+    rc::Rc,
+};
+
+use serde::{
+    de::{
+        self, DeserializeOwned, Deserializer, IntoDeserializer, MapAccess, SeqAccess, Visitor,
+        value::MapDeserializer,
+    },
+    ser::{self, Impossible, SerializeMap, SerializeStruct, Serializer},
+};
+
+use crate::{class::LoxClass, instance::LoxInstance, token::Literal, value::Value};
+
+/// The class of instances made from Rust maps.
+const MAP_CLASS: &str = "Object";
+
+/// Why a value could not be converted.
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+#[error("{0}")]
+pub struct Error(String);
+
+impl ser::Error for Error {
+    fn custom<T: Display>(message: T) -> Self {
+        Error(message.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: Display>(message: T) -> Self {
+        Error(message.to_string())
+    }
+}
+
+/// Converts a Rust value into a Lox value.
+pub fn to_value<T: ser::Serialize + ?Sized>(value: &T) -> Result<Value, Error> {
+    value.serialize(ValueSerializer)
+}
+
+/// Converts a Lox value into a Rust value.
+pub fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, Error> {
+    T::deserialize(value)
+}
+
+/// Why sequences and tuples can't be converted either way.
+const NO_LISTS: &str = "Lox has no lists, so sequences can't be converted.";
+
+fn unsupported(what: &str) -> Error {
+    Error(format!("Lox has no equivalent of {}.", what))
+}
+
+fn new_instance(class_name: &str) -> LoxInstance {
+    LoxInstance::new(Rc::new(LoxClass::new(
+        class_name.to_string(),
+        None,
+        HashMap::new(),
+    )))
+}
+
+/// The instances whose fields are being converted, outermost first. Meeting
+/// one of them again means the value contains itself.
+type Ancestors = RefCell<HashSet<*const RefCell<LoxInstance>>>;
+
+/// Runs `convert` on the fields of `instance`, unless it is already being
+/// converted further out.
+fn enter<T, E: ser::Error>(
+    instance: &Rc<RefCell<LoxInstance>>,
+    ancestors: &Ancestors,
+    convert: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    let address = Rc::as_ptr(instance);
+    if !ancestors.borrow_mut().insert(address) {
+        return Err(E::custom("cyclic instance"));
+    }
+
+    let result = convert();
+    ancestors.borrow_mut().remove(&address);
+    result
+}
+
+impl ser::Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Tracked {
+            value: self,
+            ancestors: &Ancestors::default(),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// A value being serialized, together with the instances it is nested in.
+struct Tracked<'a> {
+    value: &'a Value,
+    ancestors: &'a Ancestors,
+}
+
+impl ser::Serialize for Tracked<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.value {
+            Value::String(string) => serializer.serialize_str(string),
+            Value::Number(number) => serializer.serialize_f64(*number),
+            Value::Boolean(boolean) => serializer.serialize_bool(*boolean),
+            Value::Nil => serializer.serialize_unit(),
+            Value::Instance(instance) => enter(instance, self.ancestors, || {
+                let instance = instance.borrow();
+                let mut fields: Vec<(&str, &Value)> = instance.fields().collect();
+                fields.sort_by_key(|(name, _)| *name);
+
+                let mut map = serializer.serialize_map(Some(fields.len()))?;
+                for (name, value) in fields {
+                    let value = Tracked {
+                        value,
+                        ancestors: self.ancestors,
+                    };
+                    map.serialize_entry(name, &value)?;
+                }
+                map.end()
+            }),
+            Value::Function(_) | Value::Class(_) => Err(ser::Error::custom(format!(
+                "cannot serialize {}",
+                self.value.value_type()
+            ))),
+        }
+    }
+}
+
+impl<'de> de::Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a string, number, boolean, nil or map")
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<Value, E> {
+        Ok(Value::Boolean(value))
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Value, E> {
+        Ok(Value::Number(value as f64))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Value, E> {
+        Ok(Value::Number(value as f64))
+    }
+
+    fn visit_f64<E>(self, value: f64) -> Result<Value, E> {
+        Ok(Value::Number(value))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Value, E> {
+        Ok(Value::from(value))
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Nil)
+    }
+
+    fn visit_none<E>(self) -> Result<Value, E> {
+        Ok(Value::Nil)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        de::Deserialize::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, _seq: A) -> Result<Value, A::Error> {
+        Err(de::Error::custom(NO_LISTS))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut instance = new_instance(MAP_CLASS);
+        while let Some((name, value)) = map.next_entry::<String, Value>()? {
+            instance.set_field(name, value);
+        }
+        Ok(Value::Instance(Rc::new(RefCell::new(instance))))
+    }
+}
+
+impl ser::Serialize for Literal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Literal::String(string) => serializer.serialize_str(string),
+            Literal::Number(number) => serializer.serialize_f64(*number),
+            Literal::Boolean(boolean) => serializer.serialize_bool(*boolean),
+            Literal::Nil => serializer.serialize_unit(),
+        }
+    }
+}
+
+impl<'de> de::Deserialize<'de> for Literal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match <Value as de::Deserialize>::deserialize(deserializer)? {
+            Value::String(string) => Ok(Literal::String(string.to_string())),
+            Value::Number(number) => Ok(Literal::Number(number)),
+            Value::Boolean(boolean) => Ok(Literal::Boolean(boolean)),
+            Value::Nil => Ok(Literal::Nil),
+            _ => Err(de::Error::custom("a literal cannot be a map")),
+        }
+    }
+}
+
+/// Builds a [`Value`] from any serializable Rust value.
+struct ValueSerializer;
+
+impl Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    type SerializeSeq = Impossible<Value, Error>;
+    type SerializeTuple = Impossible<Value, Error>;
+    type SerializeTupleStruct = Impossible<Value, Error>;
+    type SerializeTupleVariant = Impossible<Value, Error>;
+    type SerializeMap = InstanceSerializer;
+    type SerializeStruct = InstanceSerializer;
+    type SerializeStructVariant = Impossible<Value, Error>;
+
+    fn serialize_bool(self, value: bool) -> Result<Value, Error> {
+        Ok(Value::Boolean(value))
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<Value, Error> {
+        self.serialize_f64(value.into())
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<Value, Error> {
+        self.serialize_f64(value.into())
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<Value, Error> {
+        self.serialize_f64(value.into())
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<Value, Error> {
+        self.serialize_f64(value as f64)
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<Value, Error> {
+        self.serialize_f64(value.into())
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<Value, Error> {
+        self.serialize_f64(value.into())
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<Value, Error> {
+        self.serialize_f64(value.into())
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<Value, Error> {
+        self.serialize_f64(value as f64)
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<Value, Error> {
+        self.serialize_f64(value.into())
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<Value, Error> {
+        Ok(Value::Number(value))
+    }
+
+    fn serialize_char(self, value: char) -> Result<Value, Error> {
+        Ok(Value::from(value.to_string()))
+    }
+
+    fn serialize_str(self, value: &str) -> Result<Value, Error> {
+        Ok(Value::from(value))
+    }
+
+    fn serialize_bytes(self, _value: &[u8]) -> Result<Value, Error> {
+        Err(unsupported("bytes"))
+    }
+
+    fn serialize_none(self) -> Result<Value, Error> {
+        Ok(Value::Nil)
+    }
+
+    fn serialize_some<T: ser::Serialize + ?Sized>(self, value: &T) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, Error> {
+        Ok(Value::Nil)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Error> {
+        Ok(Value::Nil)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Value, Error> {
+        Ok(Value::from(variant))
+    }
+
+    fn serialize_newtype_struct<T: ser::Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ser::Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Value, Error> {
+        Err(unsupported("enum variants with data"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Err(Error(NO_LISTS.to_string()))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+        Err(Error(NO_LISTS.to_string()))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Err(Error(NO_LISTS.to_string()))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(unsupported("enum variants with data"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Ok(InstanceSerializer::new(MAP_CLASS))
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        Ok(InstanceSerializer::new(name))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(unsupported("enum variants with data"))
+    }
+}
+
+/// Builds an instance from a struct or map, one field per entry.
+struct InstanceSerializer {
+    instance: LoxInstance,
+    key: Option<String>,
+}
+
+impl InstanceSerializer {
+    fn new(class_name: &str) -> Self {
+        InstanceSerializer {
+            instance: new_instance(class_name),
+            key: None,
+        }
+    }
+
+    fn finish(self) -> Value {
+        Value::Instance(Rc::new(RefCell::new(self.instance)))
+    }
+}
+
+impl SerializeMap for InstanceSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_key<T: ser::Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        match key.serialize(ValueSerializer)? {
+            Value::String(key) => {
+                self.key = Some(key.to_string());
+                Ok(())
+            }
+            key => Err(Error(format!(
+                "field names must be strings, got {}",
+                key.value_type()
+            ))),
+        }
+    }
+
+    fn serialize_value<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .expect("serde serializes a key before its value");
+        self.instance
+            .set_field(key, value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl SerializeStruct for InstanceSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: ser::Serialize + ?Sized>(
+        &mut self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.instance
+            .set_field(name.to_string(), value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Value {
+    type Deserializer = Value;
+
+    fn into_deserializer(self) -> Value {
+        self
+    }
+}
+
+impl Value {
+    /// The number as an integer, if it is one.
+    fn as_integer(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) if number.fract() == 0.0 => Some(*number),
+            _ => None,
+        }
+    }
+
+    fn invalid_type<'a>(&'a self, unexpected: &'a str) -> de::Unexpected<'a> {
+        match self {
+            Value::String(string) => de::Unexpected::Str(string),
+            Value::Number(number) => de::Unexpected::Float(*number),
+            Value::Boolean(boolean) => de::Unexpected::Bool(*boolean),
+            Value::Nil => de::Unexpected::Unit,
+            _ => de::Unexpected::Other(unexpected),
+        }
+    }
+}
+
+/// Integers are deserialized from numbers without a fractional part.
+macro_rules! deserialize_integer {
+    ($($method:ident),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                match self.value.as_integer() {
+                    Some(number) if number < 0.0 => visitor.visit_i64(number as i64),
+                    Some(number) => visitor.visit_u64(number as u64),
+                    None => self.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
+/// Deserializes a value nested in the instances in `ancestors`.
+struct ValueDeserializer<'a> {
+    value: Value,
+    ancestors: &'a Ancestors,
+}
+
+impl<'de> IntoDeserializer<'de, Error> for ValueDeserializer<'_> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> Deserializer<'de> for ValueDeserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Value::String(string) => visitor.visit_string(string.to_string()),
+            Value::Number(number) => visitor.visit_f64(number),
+            Value::Boolean(boolean) => visitor.visit_bool(boolean),
+            Value::Nil => visitor.visit_unit(),
+            Value::Instance(instance) => enter(&instance, self.ancestors, || {
+                let fields: Vec<(String, ValueDeserializer)> = instance
+                    .borrow()
+                    .fields()
+                    .map(|(name, value)| {
+                        let value = ValueDeserializer {
+                            value: value.clone(),
+                            ancestors: self.ancestors,
+                        };
+                        (name.to_string(), value)
+                    })
+                    .collect();
+                let mut map = MapDeserializer::new(fields.into_iter());
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }),
+            Value::Function(_) | Value::Class(_) => Err(de::Error::invalid_type(
+                self.value
+                    .invalid_type(&self.value.value_type().to_string()),
+                &visitor,
+            )),
+        }
+    }
+
+    deserialize_integer!(
+        deserialize_i8,
+        deserialize_i16,
+        deserialize_i32,
+        deserialize_i64,
+        deserialize_u8,
+        deserialize_u16,
+        deserialize_u32,
+        deserialize_u64
+    );
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Value::Nil => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.value {
+            Value::String(variant) => visitor.visit_enum(variant.to_string().into_deserializer()),
+            value => Err(de::Error::invalid_type(
+                value.invalid_type("a value"),
+                &"a variant name",
+            )),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        Err(Error(NO_LISTS.to_string()))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        _visitor: V,
+    ) -> Result<V::Value, Error> {
+        Err(Error(NO_LISTS.to_string()))
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        _visitor: V,
+    ) -> Result<V::Value, Error> {
+        Err(Error(NO_LISTS.to_string()))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool f32 f64 char str string bytes byte_buf unit unit_struct map struct
+        identifier ignored_any
+    }
+}
+
+/// Forwards to a [`ValueDeserializer`] for a value nested in nothing.
+macro_rules! forward_to_value_deserializer {
+    ($($method:ident($($argument:ident: $type:ty),*)),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(
+                self,
+                $($argument: $type,)*
+                visitor: V,
+            ) -> Result<V::Value, Error> {
+                let ancestors = Ancestors::default();
+                ValueDeserializer {
+                    value: self,
+                    ancestors: &ancestors,
+                }
+                .$method($($argument,)* visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Value {
+    type Error = Error;
+
+    forward_to_value_deserializer!(
+        deserialize_any(),
+        deserialize_bool(),
+        deserialize_i8(),
+        deserialize_i16(),
+        deserialize_i32(),
+        deserialize_i64(),
+        deserialize_u8(),
+        deserialize_u16(),
+        deserialize_u32(),
+        deserialize_u64(),
+        deserialize_f32(),
+        deserialize_f64(),
+        deserialize_char(),
+        deserialize_str(),
+        deserialize_string(),
+        deserialize_bytes(),
+        deserialize_byte_buf(),
+        deserialize_option(),
+        deserialize_unit(),
+        deserialize_unit_struct(name: &'static str),
+        deserialize_newtype_struct(name: &'static str),
+        deserialize_seq(),
+        deserialize_tuple(length: usize),
+        deserialize_tuple_struct(name: &'static str, length: usize),
+        deserialize_map(),
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]),
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]),
+        deserialize_identifier(),
+        deserialize_ignored_any(),
+    );
+}
@@ -0,0 +1,316 @@
+use std::fmt::Write;
+
+use crate::{
+    interpreter::{RuntimeErrorKind, StackFrame},
+    rlox::RloxError,
+    token::Span,
+};
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Frames shown from each end of a long backtrace.
+const BACKTRACE_ENDS: usize = 10;
+
+/// A single error ready to be shown to the user, independent of which stage
+/// produced it.
+pub struct Diagnostic {
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub span: Span,
+    pub notes: Vec<String>,
+    pub help: Vec<String>,
+    /// For runtime errors, the calls in progress, innermost first.
+    pub backtrace: Vec<StackFrame>,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Diagnostic {
+            code: None,
+            message: message.into(),
+            span,
+            notes: vec![],
+            help: vec![],
+            backtrace: vec![],
+        }
+    }
+
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help.push(help.into());
+        self
+    }
+
+    /// Serializes the diagnostic as a single-line JSON object for editors and
+    /// CI tooling.
+    pub fn to_json(&self, file_name: &str) -> String {
+        let span = self.span;
+
+        format!(
+            concat!(
+                "{{\"severity\":\"error\",\"code\":{},\"message\":{},\"file\":{},",
+                "\"span\":{{\"start\":{},\"end\":{},\"line\":{},\"column\":{},",
+                "\"end_line\":{},\"end_column\":{}}},\"notes\":{},\"help\":{},",
+                "\"backtrace\":[{}]}}"
+            ),
+            self.code.map_or("null".to_string(), json_string),
+            json_string(&self.message),
+            json_string(file_name),
+            span.start,
+            span.end,
+            span.line,
+            span.column,
+            span.end_line,
+            span.end_column,
+            json_array(&self.notes),
+            json_array(&self.help),
+            self.backtrace
+                .iter()
+                .map(json_frame)
+                .collect::<Vec<_>>()
+                .join(",")
+        )
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut output = String::with_capacity(text.len() + 2);
+    output.push('"');
+
+    for c in text.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(output, "\\u{:04x}", c as u32);
+            }
+            c => output.push(c),
+        }
+    }
+
+    output.push('"');
+    output
+}
+
+fn json_frame(frame: &StackFrame) -> String {
+    format!(
+        "{{\"file\":{},\"function\":{},\"line\":{},\"column\":{}}}",
+        frame
+            .file
+            .as_deref()
+            .map_or("null".to_string(), json_string),
+        frame
+            .function
+            .as_deref()
+            .map_or("null".to_string(), json_string),
+        frame.span.line,
+        frame.span.column
+    )
+}
+
+fn json_array(items: &[String]) -> String {
+    let items: Vec<String> = items.iter().map(|item| json_string(item)).collect();
+    format!("[{}]", items.join(","))
+}
+
+impl From<&RloxError> for Diagnostic {
+    fn from(error: &RloxError) -> Self {
+        match error {
+            RloxError::Scan(error) => {
+                Diagnostic::new(&error.message, error.span).with_code(error.code)
+            }
+            RloxError::Parse(error) => {
+                Diagnostic::new(&error.message, error.span).with_code(error.code)
+            }
+            RloxError::Resolve(error) => {
+                Diagnostic::new(&error.message, error.span).with_code(error.code)
+            }
+            RloxError::Compile(error) => {
+                Diagnostic::new(&error.message, error.span).with_code(error.code)
+            }
+            RloxError::Runtime(error) => {
+                let mut diagnostic =
+                    Diagnostic::new(error.message(), error.span()).with_code(error.code());
+                diagnostic.backtrace = error.backtrace.clone();
+
+                match &error.kind {
+                    RuntimeErrorKind::UnsupportedOperands { left, right } => diagnostic.with_note(
+                        format!("left operand is a {}, right operand is a {}", left, right),
+                    ),
+                    RuntimeErrorKind::UndefinedVariable => diagnostic.with_help(format!(
+                        "declare it with `var {} = ...;` before using it",
+                        error.token.lexeme
+                    )),
+                    RuntimeErrorKind::ArityMismatch { expected, .. } => {
+                        diagnostic.with_note(format!("the callee takes {} arguments", expected))
+                    }
+                    _ => diagnostic,
+                }
+            }
+        }
+    }
+}
+
+/// Renders diagnostics against the source they refer to, underlining the
+/// offending span with carets.
+pub struct Renderer<'a> {
+    source: &'a str,
+    file_name: &'a str,
+    color: bool,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(source: &'a str, file_name: &'a str, color: bool) -> Self {
+        Renderer {
+            source,
+            file_name,
+            color,
+        }
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let mut output = String::new();
+        let span = diagnostic.span;
+
+        let title = match diagnostic.code {
+            Some(code) => format!("error[{}]", code),
+            None => "error".to_string(),
+        };
+        let _ = writeln!(
+            output,
+            "{}{}",
+            self.paint(RED, &title),
+            self.paint(BOLD, &format!(": {}", diagnostic.message))
+        );
+
+        let line_number = span.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+
+        let _ = writeln!(
+            output,
+            "{}{} {}:{}:{}",
+            gutter,
+            self.paint(BLUE, "-->"),
+            self.file_name,
+            span.line,
+            span.column
+        );
+
+        if let Some(line) = self.source_line(span.line) {
+            let bar = self.paint(BLUE, "|");
+            let _ = writeln!(output, "{} {}", gutter, bar);
+            let _ = writeln!(
+                output,
+                "{} {} {}",
+                self.paint(BLUE, &line_number),
+                bar,
+                line
+            );
+            let _ = writeln!(
+                output,
+                "{} {} {}{}",
+                gutter,
+                bar,
+                Self::padding(line, span.column),
+                self.paint(RED, &"^".repeat(Self::underline_width(line, span)))
+            );
+        }
+
+        for note in &diagnostic.notes {
+            let _ = writeln!(
+                output,
+                "{} {} note: {}",
+                gutter,
+                self.paint(BLUE, "="),
+                note
+            );
+        }
+
+        for help in &diagnostic.help {
+            let _ = writeln!(
+                output,
+                "{} {} help: {}",
+                gutter,
+                self.paint(BLUE, "="),
+                help
+            );
+        }
+
+        // Runaway recursion leaves hundreds of frames; the ends show how it
+        // started and where it failed.
+        let frames = &diagnostic.backtrace;
+        if frames.len() > 2 * BACKTRACE_ENDS + 1 {
+            for frame in &frames[..BACKTRACE_ENDS] {
+                let _ = writeln!(output, "{}", frame);
+            }
+            let _ = writeln!(
+                output,
+                "... {} more frames",
+                frames.len() - 2 * BACKTRACE_ENDS
+            );
+            for frame in &frames[frames.len() - BACKTRACE_ENDS..] {
+                let _ = writeln!(output, "{}", frame);
+            }
+        } else {
+            for frame in frames {
+                let _ = writeln!(output, "{}", frame);
+            }
+        }
+
+        output
+    }
+
+    fn source_line(&self, line: usize) -> Option<&str> {
+        if line == 0 {
+            return None;
+        }
+
+        self.source.lines().nth(line - 1)
+    }
+
+    /// Whitespace lining the carets up with `column`, keeping tabs so the
+    /// alignment survives terminals with any tab width.
+    fn padding(line: &str, column: usize) -> String {
+        line.chars()
+            .take(column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect()
+    }
+
+    /// Number of carets under `span` on its first line. Spans running onto
+    /// later lines are underlined to the end of the first one.
+    fn underline_width(line: &str, span: Span) -> usize {
+        let line_length = line.chars().count();
+
+        let end_column = if span.end_line == span.line {
+            span.end_column
+        } else {
+            line_length + 1
+        };
+
+        end_column.saturating_sub(span.column).max(1)
+    }
+}
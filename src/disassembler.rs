@@ -0,0 +1,158 @@
+use std::fmt::Write;
+
+use crate::chunk::{Chunk, Constant, FunctionProto, OpCode};
+
+/// Renders compiled bytecode as a human-readable listing: one instruction per
+/// line with its offset, source line and decoded operands.
+pub struct Disassembler;
+
+impl Disassembler {
+    /// Disassembles `function` followed by every function nested in it.
+    pub fn disassemble(&self, function: &FunctionProto) -> String {
+        let mut output = String::new();
+        self.disassemble_function(function, &mut output);
+        output
+    }
+
+    fn disassemble_function(&self, function: &FunctionProto, output: &mut String) {
+        let _ = writeln!(output, "== {} ==", function.name);
+
+        let chunk = &function.chunk;
+        let mut offset = 0;
+        while offset < chunk.code.len() {
+            let (line, next) = self.disassemble_instruction(chunk, offset);
+            output.push_str(&line);
+            offset = next;
+        }
+
+        for constant in &chunk.constants {
+            if let Constant::Function(nested) = constant {
+                output.push('\n');
+                self.disassemble_function(nested, output);
+            }
+        }
+    }
+
+    /// Disassembles the instruction at `offset`, returning its listing and
+    /// the offset of the next instruction.
+    pub fn disassemble_instruction(&self, chunk: &Chunk, offset: usize) -> (String, usize) {
+        let mut output = format!("{:04} ", offset);
+
+        let line = chunk.span_at(offset).line;
+        if offset > 0 && chunk.span_at(offset - 1).line == line {
+            output.push_str("   | ");
+        } else {
+            let _ = write!(output, "{:4} ", line);
+        }
+
+        let op = match OpCode::try_from(chunk.code[offset]) {
+            Ok(op) => op,
+            Err(byte) => {
+                let _ = writeln!(output, "Unknown opcode {}", byte);
+                return (output, offset + 1);
+            }
+        };
+        let name = format!("{:?}", op);
+
+        let next = match op {
+            OpCode::Constant
+            | OpCode::GetGlobal
+            | OpCode::DefineGlobal
+            | OpCode::SetGlobal
+            | OpCode::GetProperty
+            | OpCode::SetProperty
+            | OpCode::GetSuper => {
+                let index = chunk.read_u16(offset + 1);
+                let constant = &chunk.constants[index as usize];
+                let _ = writeln!(output, "{:<16} {:4} '{}'", name, index, constant);
+                offset + 3
+            }
+            OpCode::GetLocal | OpCode::SetLocal | OpCode::GetUpvalue | OpCode::SetUpvalue => {
+                let _ = writeln!(output, "{:<16} {:4}", name, chunk.read_u16(offset + 1));
+                offset + 3
+            }
+            OpCode::Call => {
+                let _ = writeln!(output, "{:<16} {:4}", name, chunk.code[offset + 1]);
+                offset + 2
+            }
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => {
+                let distance = chunk.read_u16(offset + 1) as usize;
+                let target = if op == OpCode::Loop {
+                    offset + 3 - distance
+                } else {
+                    offset + 3 + distance
+                };
+                let _ = writeln!(output, "{:<16} {:4} -> {:04}", name, distance, target);
+                offset + 3
+            }
+            OpCode::Closure => {
+                let index = chunk.read_u16(offset + 1);
+                let constant = &chunk.constants[index as usize];
+                let _ = writeln!(output, "{:<16} {:4} {}", name, index, constant);
+
+                let upvalue_count = match constant {
+                    Constant::Function(function) => function.upvalue_count,
+                    _ => 0,
+                };
+
+                let mut next = offset + 3;
+                for _ in 0..upvalue_count {
+                    let kind = if chunk.code[next] == 1 {
+                        "local"
+                    } else {
+                        "upvalue"
+                    };
+                    let _ = writeln!(
+                        output,
+                        "{:04}    |                     {} {}",
+                        next,
+                        kind,
+                        chunk.read_u16(next + 1)
+                    );
+                    next += 3;
+                }
+                next
+            }
+            OpCode::Class => {
+                let index = chunk.read_u16(offset + 1);
+                let methods = chunk.read_u16(offset + 3);
+                let superclass = if chunk.code[offset + 5] == 1 {
+                    " < superclass"
+                } else {
+                    ""
+                };
+                let _ = writeln!(
+                    output,
+                    "{:<16} {:4} '{}' methods={}{}",
+                    name, index, chunk.constants[index as usize], methods, superclass
+                );
+                offset + 6
+            }
+            OpCode::Nil
+            | OpCode::True
+            | OpCode::False
+            | OpCode::Pop
+            | OpCode::Equal
+            | OpCode::NotEqual
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::Comma
+            | OpCode::Not
+            | OpCode::Negate
+            | OpCode::Print
+            | OpCode::CloseUpvalue
+            | OpCode::Return => {
+                let _ = writeln!(output, "{}", name);
+                offset + 1
+            }
+        };
+
+        (output, next)
+    }
+}
@@ -1,32 +1,113 @@
-use std::collections::HashMap;
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 use crate::{
-    interpreter::RuntimeError,
-    token::{Literal, Token},
+    heap::Trace,
+    interpreter::{RuntimeError, RuntimeErrorKind},
+    token::Token,
+    value::Value,
 };
 
 pub struct Environment {
-    values: HashMap<String, Literal>,
+    values: HashMap<String, Value>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Environment {
     pub fn new() -> Self {
         Environment {
             values: HashMap::new(),
+            enclosing: None,
+        }
+    }
+
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Environment {
+            values: HashMap::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    pub fn get(&self, name: &Token) -> Result<Value, RuntimeError> {
+        if let Some(value) = self.values.get(&name.lexeme) {
+            return Ok(value.clone());
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow().get(name),
+            None => Err(Self::undefined(name)),
+        }
+    }
+
+    /// Reads `name` from the environment exactly `distance` hops up the chain.
+    pub fn get_at(&self, distance: usize, name: &str) -> Value {
+        if distance == 0 {
+            return self.values.get(name).cloned().unwrap_or(Value::Nil);
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow().get_at(distance - 1, name),
+            None => Value::Nil,
+        }
+    }
+
+    /// Overwrites `name` in the environment exactly `distance` hops up the chain.
+    pub fn assign_at(&mut self, distance: usize, name: &Token, value: Value) {
+        if distance == 0 {
+            self.values.insert(name.lexeme.clone(), value);
+            return;
+        }
+
+        if let Some(enclosing) = &self.enclosing {
+            enclosing.borrow_mut().assign_at(distance - 1, name, value);
         }
     }
 
-    pub fn get(&self, name: &Token) -> Result<&Literal, RuntimeError> {
-        dbg!(&self.values.get(&name.lexeme));
-        self.values.get(&name.lexeme).ok_or(RuntimeError {
-            message: format!("Undefined variable \"{}\".", name.lexeme),
-            line: name.line,
-        })
+    /// Reads `name` from this scope only.
+    pub fn lookup(&self, name: &str) -> Option<Value> {
+        self.values.get(name).cloned()
     }
 
-    pub fn define(&mut self, name: String, value: Literal) -> Result<(), RuntimeError> {
-        dbg!(&name, &value);
+    pub fn define(&mut self, name: String, value: Value) {
         self.values.insert(name, value);
-        Ok(())
+    }
+
+    pub fn assign(&mut self, name: &Token, value: Value) -> Result<(), RuntimeError> {
+        if let Some(slot) = self.values.get_mut(&name.lexeme) {
+            *slot = value;
+            return Ok(());
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+            None => Err(Self::undefined(name)),
+        }
+    }
+
+    /// Drops every variable and the link to the enclosing scope.
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.enclosing = None;
+    }
+
+    fn undefined(name: &Token) -> RuntimeError {
+        RuntimeError::new(RuntimeErrorKind::UndefinedVariable, name)
+    }
+}
+
+impl Trace for Environment {
+    fn trace(&self, visit: &mut dyn FnMut(*const ())) {
+        for value in self.values.values() {
+            value.trace(visit);
+        }
+
+        if let Some(enclosing) = &self.enclosing {
+            visit(Rc::as_ptr(enclosing) as *const ());
+        }
     }
 }
@@ -1,27 +1,108 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use crate::token::*;
 
+/// Identifies an expression that refers to a variable so the resolver can
+/// record where its binding lives in a side table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ExprId(usize);
+
+impl ExprId {
+    pub fn next() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        ExprId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
 pub enum Expr {
+    Assign {
+        name: Token,
+        value: Box<Expr>,
+        id: ExprId,
+        span: Span,
+    },
     Unary {
         operator: Token,
         right: Box<Expr>,
+        span: Span,
     },
     Variable {
         name: Token,
+        id: ExprId,
+        span: Span,
     },
     Grouping {
         expression: Box<Expr>,
+        span: Span,
     },
     Ternary {
         condition: Box<Expr>,
         positive: Box<Expr>,
         negative: Box<Expr>,
+        span: Span,
     },
     Binary {
         left: Box<Expr>,
         operator: Token,
         right: Box<Expr>,
+        span: Span,
     },
     Literal {
         value: Literal,
+        span: Span,
+    },
+    Call {
+        callee: Box<Expr>,
+        paren: Token,
+        arguments: Vec<Expr>,
+        span: Span,
     },
+    Get {
+        object: Box<Expr>,
+        name: Token,
+        span: Span,
+    },
+    Set {
+        object: Box<Expr>,
+        name: Token,
+        value: Box<Expr>,
+        span: Span,
+    },
+    This {
+        keyword: Token,
+        id: ExprId,
+        span: Span,
+    },
+    Super {
+        keyword: Token,
+        method: Token,
+        id: ExprId,
+        span: Span,
+    },
+    Logical {
+        left: Box<Expr>,
+        operator: Token,
+        right: Box<Expr>,
+        span: Span,
+    },
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Assign { span, .. }
+            | Expr::Unary { span, .. }
+            | Expr::Variable { span, .. }
+            | Expr::Grouping { span, .. }
+            | Expr::Ternary { span, .. }
+            | Expr::Binary { span, .. }
+            | Expr::Literal { span, .. }
+            | Expr::Call { span, .. }
+            | Expr::Get { span, .. }
+            | Expr::Set { span, .. }
+            | Expr::This { span, .. }
+            | Expr::Super { span, .. }
+            | Expr::Logical { span, .. } => *span,
+        }
+    }
 }
@@ -0,0 +1,213 @@
+use std::{
+    cell::RefCell,
+    fmt::{self, Display},
+    rc::Rc,
+};
+
+use crate::{
+    environment::Environment,
+    heap::{Heap, Trace},
+    instance::LoxInstance,
+    interpreter::{Interpreter, Locals, RuntimeError, RuntimeErrorKind, Unwind},
+    native::NativeFn,
+    stmt::FunctionDecl,
+    token::Token,
+    value::Value,
+    vm::Closure,
+};
+
+pub struct LoxFunction {
+    body: FunctionBody,
+}
+
+/// How a function is executed, which depends on the backend that created it.
+pub(crate) enum FunctionBody {
+    /// Walked by the [`Interpreter`] in the environment it closed over, with
+    /// the scope depths of the program that declared it.
+    Interpreted {
+        declaration: Rc<FunctionDecl>,
+        closure: Rc<RefCell<Environment>>,
+        locals: Rc<Locals>,
+        is_initializer: bool,
+    },
+    /// Compiled to bytecode and run by the VM. `receiver` is set once a
+    /// method has been bound to an instance.
+    Compiled {
+        closure: Rc<Closure>,
+        receiver: Option<Rc<RefCell<LoxInstance>>>,
+    },
+    /// Implemented by the host and callable from either backend.
+    Native {
+        name: String,
+        arity: usize,
+        function: NativeFn,
+    },
+}
+
+impl LoxFunction {
+    pub(crate) fn new(
+        declaration: Rc<FunctionDecl>,
+        closure: Rc<RefCell<Environment>>,
+        locals: Rc<Locals>,
+        is_initializer: bool,
+    ) -> Self {
+        LoxFunction {
+            body: FunctionBody::Interpreted {
+                declaration,
+                closure,
+                locals,
+                is_initializer,
+            },
+        }
+    }
+
+    pub(crate) fn compiled(closure: Rc<Closure>) -> Self {
+        LoxFunction {
+            body: FunctionBody::Compiled {
+                closure,
+                receiver: None,
+            },
+        }
+    }
+
+    pub(crate) fn native(name: String, arity: usize, function: NativeFn) -> Self {
+        LoxFunction {
+            body: FunctionBody::Native {
+                name,
+                arity,
+                function,
+            },
+        }
+    }
+
+    pub(crate) fn body(&self) -> &FunctionBody {
+        &self.body
+    }
+
+    pub fn name(&self) -> &str {
+        match &self.body {
+            FunctionBody::Interpreted { declaration, .. } => &declaration.name.lexeme,
+            FunctionBody::Compiled { closure, .. } => &closure.function.name,
+            FunctionBody::Native { name, .. } => name,
+        }
+    }
+
+    /// Returns a copy of this method with `this` bound to `instance`.
+    pub(crate) fn bind(
+        &self,
+        instance: Rc<RefCell<LoxInstance>>,
+        heap: &mut Heap,
+    ) -> Rc<LoxFunction> {
+        let function = match &self.body {
+            FunctionBody::Interpreted {
+                declaration,
+                closure,
+                locals,
+                is_initializer,
+            } => {
+                let mut environment = Environment::with_enclosing(Rc::clone(closure));
+                environment.define("this".to_string(), Value::Instance(instance));
+
+                LoxFunction::new(
+                    Rc::clone(declaration),
+                    heap.environment(environment),
+                    Rc::clone(locals),
+                    *is_initializer,
+                )
+            }
+            FunctionBody::Compiled { closure, .. } => LoxFunction {
+                body: FunctionBody::Compiled {
+                    closure: Rc::clone(closure),
+                    receiver: Some(instance),
+                },
+            },
+            FunctionBody::Native { .. } => unreachable!("natives are never methods"),
+        };
+
+        heap.function(function)
+    }
+
+    pub fn is_native(&self) -> bool {
+        matches!(self.body, FunctionBody::Native { .. })
+    }
+
+    pub fn arity(&self) -> usize {
+        match &self.body {
+            FunctionBody::Interpreted { declaration, .. } => declaration.params.len(),
+            FunctionBody::Compiled { closure, .. } => closure.function.arity,
+            FunctionBody::Native { arity, .. } => *arity,
+        }
+    }
+
+    /// Calls the function from the interpreter. `paren` is the call's
+    /// closing parenthesis, where errors raised by natives are reported.
+    pub(crate) fn call(
+        &self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<Value>,
+        paren: &Token,
+    ) -> Result<Value, RuntimeError> {
+        let (declaration, closure, locals, is_initializer) = match &self.body {
+            FunctionBody::Interpreted {
+                declaration,
+                closure,
+                locals,
+                is_initializer,
+            } => (declaration, closure, locals, is_initializer),
+            FunctionBody::Native { function, .. } => {
+                let result = function(&arguments).map_err(|kind| RuntimeError::new(kind, paren))?;
+                interpreter.heap_mut().string(&result);
+                return Ok(result);
+            }
+            FunctionBody::Compiled { .. } => {
+                return Err(RuntimeError::new(RuntimeErrorKind::ForeignFunction, paren));
+            }
+        };
+
+        let mut environment = Environment::with_enclosing(Rc::clone(closure));
+
+        for (param, argument) in declaration.params.iter().zip(arguments) {
+            environment.define(param.lexeme.clone(), argument);
+        }
+
+        let environment = interpreter.heap_mut().environment(environment);
+        let enclosing = interpreter.replace_locals(Rc::clone(locals));
+        let result = interpreter.execute_block(&declaration.body, environment);
+        interpreter.replace_locals(enclosing);
+
+        match result {
+            // Initializers always hand back the instance, even on a bare `return;`.
+            Ok(()) | Err(Unwind::Return(_)) if *is_initializer => {
+                Ok(closure.borrow().get_at(0, "this"))
+            }
+            Ok(()) => Ok(Value::Nil),
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(error)) => Err(error),
+        }
+    }
+}
+
+impl Trace for LoxFunction {
+    fn trace(&self, visit: &mut dyn FnMut(*const ())) {
+        match &self.body {
+            FunctionBody::Interpreted { closure, .. } => visit(Rc::as_ptr(closure) as *const ()),
+            FunctionBody::Compiled { closure, receiver } => {
+                visit(Rc::as_ptr(closure) as *const ());
+
+                if let Some(receiver) = receiver {
+                    visit(Rc::as_ptr(receiver) as *const ());
+                }
+            }
+            FunctionBody::Native { .. } => {}
+        }
+    }
+}
+
+impl Display for LoxFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.body {
+            FunctionBody::Native { .. } => write!(f, "<native fn {}>", self.name()),
+            _ => write!(f, "<fn {}>", self.name()),
+        }
+    }
+}
@@ -0,0 +1,523 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    mem::size_of,
+    rc::{Rc, Weak},
+};
+
+use crate::{
+    class::LoxClass,
+    environment::Environment,
+    function::LoxFunction,
+    instance::LoxInstance,
+    value::Value,
+    vm::{Closure, Upvalue},
+};
+
+/// Implemented by everything that can hold references to heap objects.
+pub trait Trace {
+    /// Calls `visit` with the address of every heap object directly
+    /// referenced by `self`.
+    fn trace(&self, visit: &mut dyn FnMut(*const ()));
+}
+
+/// When the collector runs.
+#[derive(Clone, Copy, Debug)]
+pub struct GcConfig {
+    /// Estimated bytes allocated before the first collection.
+    pub initial_threshold: usize,
+    /// After a collection, the next one runs once the heap has grown to this
+    /// multiple of what survived.
+    pub growth_factor: usize,
+    /// Collect on every allocation, to shake out rooting bugs.
+    pub stress: bool,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        GcConfig {
+            initial_threshold: 1024 * 1024,
+            growth_factor: 2,
+            stress: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GcStats {
+    pub collections: usize,
+    /// Objects reclaimed by the collector. Objects freed by reference
+    /// counting alone aren't included.
+    pub objects_freed: usize,
+    pub bytes_freed: usize,
+    /// Estimated size of the objects tracked by the heap, strings included.
+    pub bytes_allocated: usize,
+}
+
+/// A strong reference to any kind of heap object.
+enum Object {
+    Environment(Rc<RefCell<Environment>>),
+    Instance(Rc<RefCell<LoxInstance>>),
+    Function(Rc<LoxFunction>),
+    Class(Rc<LoxClass>),
+    Closure(Rc<Closure>),
+    Upvalue(Rc<RefCell<Upvalue>>),
+    String(Rc<str>),
+}
+
+enum WeakObject {
+    Environment(Weak<RefCell<Environment>>),
+    Instance(Weak<RefCell<LoxInstance>>),
+    Function(Weak<LoxFunction>),
+    Class(Weak<LoxClass>),
+    Closure(Weak<Closure>),
+    Upvalue(Weak<RefCell<Upvalue>>),
+    String(Weak<str>),
+}
+
+struct Entry {
+    object: WeakObject,
+    size: usize,
+}
+
+impl Object {
+    fn address(&self) -> *const () {
+        match self {
+            Object::Environment(object) => Rc::as_ptr(object) as *const (),
+            Object::Instance(object) => Rc::as_ptr(object) as *const (),
+            Object::Function(object) => Rc::as_ptr(object) as *const (),
+            Object::Class(object) => Rc::as_ptr(object) as *const (),
+            Object::Closure(object) => Rc::as_ptr(object) as *const (),
+            Object::Upvalue(object) => Rc::as_ptr(object) as *const (),
+            Object::String(object) => Rc::as_ptr(object) as *const u8 as *const (),
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Object::Environment(object) => Rc::strong_count(object),
+            Object::Instance(object) => Rc::strong_count(object),
+            Object::Function(object) => Rc::strong_count(object),
+            Object::Class(object) => Rc::strong_count(object),
+            Object::Closure(object) => Rc::strong_count(object),
+            Object::Upvalue(object) => Rc::strong_count(object),
+            Object::String(object) => Rc::strong_count(object),
+        }
+    }
+
+    /// Visits the object's references. Returns false if the object is
+    /// borrowed right now and couldn't be inspected.
+    fn trace(&self, visit: &mut dyn FnMut(*const ())) -> bool {
+        fn traced<T: Trace>(cell: &RefCell<T>, visit: &mut dyn FnMut(*const ())) -> bool {
+            match cell.try_borrow() {
+                Ok(object) => {
+                    object.trace(visit);
+                    true
+                }
+                Err(_) => false,
+            }
+        }
+
+        match self {
+            Object::Environment(object) => traced(object, visit),
+            Object::Instance(object) => traced(object, visit),
+            Object::Upvalue(object) => traced(object, visit),
+            Object::Function(object) => {
+                object.trace(visit);
+                true
+            }
+            Object::Class(object) => {
+                object.trace(visit);
+                true
+            }
+            Object::Closure(object) => {
+                object.trace(visit);
+                true
+            }
+            Object::String(_) => true,
+        }
+    }
+
+    /// Drops the references held by a garbage object. Every cycle passes
+    /// through at least one mutable object, so clearing those is enough to
+    /// let reference counting free the rest.
+    fn clear(&self) {
+        match self {
+            Object::Environment(object) => {
+                if let Ok(mut environment) = object.try_borrow_mut() {
+                    environment.clear();
+                }
+            }
+            Object::Instance(object) => {
+                if let Ok(mut instance) = object.try_borrow_mut() {
+                    instance.clear();
+                }
+            }
+            Object::Upvalue(object) => {
+                if let Ok(mut upvalue) = object.try_borrow_mut() {
+                    *upvalue = Upvalue::Closed(Value::Nil);
+                }
+            }
+            Object::Function(_) | Object::Class(_) | Object::Closure(_) | Object::String(_) => {}
+        }
+    }
+}
+
+impl WeakObject {
+    fn upgrade(&self) -> Option<Object> {
+        match self {
+            WeakObject::Environment(object) => object.upgrade().map(Object::Environment),
+            WeakObject::Instance(object) => object.upgrade().map(Object::Instance),
+            WeakObject::Function(object) => object.upgrade().map(Object::Function),
+            WeakObject::Class(object) => object.upgrade().map(Object::Class),
+            WeakObject::Closure(object) => object.upgrade().map(Object::Closure),
+            WeakObject::Upvalue(object) => object.upgrade().map(Object::Upvalue),
+            WeakObject::String(object) => object.upgrade().map(Object::String),
+        }
+    }
+
+    fn is_alive(&self) -> bool {
+        match self {
+            WeakObject::Environment(object) => object.strong_count() > 0,
+            WeakObject::Instance(object) => object.strong_count() > 0,
+            WeakObject::Function(object) => object.strong_count() > 0,
+            WeakObject::Class(object) => object.strong_count() > 0,
+            WeakObject::Closure(object) => object.strong_count() > 0,
+            WeakObject::Upvalue(object) => object.strong_count() > 0,
+            WeakObject::String(object) => object.strong_count() > 0,
+        }
+    }
+}
+
+/// Allocates the runtime's reference-counted objects and collects the
+/// cycles reference counting can't free.
+///
+/// Roots are found rather than enumerated: every reference from one heap
+/// object to another is subtracted from the target's strong count, and
+/// whatever is left over comes from outside the heap, i.e. the
+/// interpreter's environment chain, the VM's stack and globals, or values
+/// held by the Rust code running right now. Objects not reachable from
+/// those are garbage.
+pub struct Heap {
+    entries: Vec<Entry>,
+    config: GcConfig,
+    stats: GcStats,
+    next_collection: usize,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        let config = GcConfig::default();
+
+        Heap {
+            entries: vec![],
+            config,
+            stats: GcStats::default(),
+            next_collection: config.initial_threshold,
+        }
+    }
+
+    pub fn set_config(&mut self, config: GcConfig) {
+        self.config = config;
+        self.next_collection = config.initial_threshold;
+    }
+
+    pub fn stats(&self) -> GcStats {
+        self.stats
+    }
+
+    pub fn environment(&mut self, environment: Environment) -> Rc<RefCell<Environment>> {
+        let object = Rc::new(RefCell::new(environment));
+        self.track(
+            WeakObject::Environment(Rc::downgrade(&object)),
+            size_of::<Environment>(),
+        );
+        object
+    }
+
+    pub fn instance(&mut self, instance: LoxInstance) -> Rc<RefCell<LoxInstance>> {
+        let object = Rc::new(RefCell::new(instance));
+        self.track(
+            WeakObject::Instance(Rc::downgrade(&object)),
+            size_of::<LoxInstance>(),
+        );
+        object
+    }
+
+    pub fn function(&mut self, function: LoxFunction) -> Rc<LoxFunction> {
+        let object = Rc::new(function);
+        self.track(
+            WeakObject::Function(Rc::downgrade(&object)),
+            size_of::<LoxFunction>(),
+        );
+        object
+    }
+
+    pub fn class(&mut self, class: LoxClass) -> Rc<LoxClass> {
+        let object = Rc::new(class);
+        self.track(
+            WeakObject::Class(Rc::downgrade(&object)),
+            size_of::<LoxClass>(),
+        );
+        object
+    }
+
+    pub fn closure(&mut self, closure: Closure) -> Rc<Closure> {
+        let size = size_of::<Closure>() + closure.upvalues.len() * size_of::<usize>();
+        let object = Rc::new(closure);
+        self.track(WeakObject::Closure(Rc::downgrade(&object)), size);
+        object
+    }
+
+    pub fn upvalue(&mut self, upvalue: Upvalue) -> Rc<RefCell<Upvalue>> {
+        let object = Rc::new(RefCell::new(upvalue));
+        self.track(
+            WeakObject::Upvalue(Rc::downgrade(&object)),
+            size_of::<Upvalue>(),
+        );
+        object
+    }
+
+    /// Counts a string made at run time, such as the result of `+`, towards
+    /// the heap's size until it is dropped. Strings hold no references, so
+    /// the collector never frees them, but they can be most of the memory a
+    /// script uses. Strings shared with anything else were counted, if at
+    /// all, when they were made.
+    pub fn string(&mut self, value: &Value) {
+        if let Value::String(string) = value
+            && Rc::strong_count(string) == 1
+        {
+            let size = size_of::<[usize; 2]>() + string.len();
+            self.track(WeakObject::String(Rc::downgrade(string)), size);
+        }
+    }
+
+    /// Registers a new object, collecting first if the heap has grown past
+    /// its threshold. The new object holds references of its own, so it is
+    /// only tracked after the collection and can't be mistaken for garbage.
+    fn track(&mut self, object: WeakObject, size: usize) {
+        if self.config.stress || self.stats.bytes_allocated + size > self.next_collection {
+            self.collect();
+        }
+
+        self.entries.push(Entry { object, size });
+        self.stats.bytes_allocated += size;
+    }
+
+    pub fn collect(&mut self) {
+        self.entries.retain(|entry| entry.object.is_alive());
+
+        let objects: Vec<Object> = self
+            .entries
+            .iter()
+            .filter_map(|entry| entry.object.upgrade())
+            .collect();
+        let index: HashMap<*const (), usize> = objects
+            .iter()
+            .enumerate()
+            .map(|(i, object)| (object.address(), i))
+            .collect();
+
+        // Strong references from outside the heap, minus our own upgrade.
+        let mut external: Vec<isize> = objects
+            .iter()
+            .map(|object| object.strong_count() as isize - 1)
+            .collect();
+        let mut roots = vec![false; objects.len()];
+
+        for (i, object) in objects.iter().enumerate() {
+            let traced = object.trace(&mut |child| {
+                if let Some(&j) = index.get(&child) {
+                    external[j] -= 1;
+                }
+            });
+
+            // A borrowed object is in use, so it must be live.
+            roots[i] = !traced;
+        }
+
+        let mut pending: Vec<usize> = (0..objects.len())
+            .filter(|&i| roots[i] || external[i] > 0)
+            .collect();
+        let mut reachable = vec![false; objects.len()];
+
+        while let Some(i) = pending.pop() {
+            if reachable[i] {
+                continue;
+            }
+            reachable[i] = true;
+
+            objects[i].trace(&mut |child| {
+                if let Some(&j) = index.get(&child)
+                    && !reachable[j]
+                {
+                    pending.push(j);
+                }
+            });
+        }
+
+        for (object, _) in objects.iter().zip(&reachable).filter(|(_, live)| !**live) {
+            object.clear();
+        }
+        drop(objects);
+
+        let mut freed = GcStats::default();
+        self.entries.retain(|entry| {
+            let alive = entry.object.is_alive();
+            if !alive {
+                freed.objects_freed += 1;
+                freed.bytes_freed += entry.size;
+            }
+            alive
+        });
+
+        self.stats.collections += 1;
+        self.stats.objects_freed += freed.objects_freed;
+        self.stats.bytes_freed += freed.bytes_freed;
+        self.stats.bytes_allocated = self.entries.iter().map(|entry| entry.size).sum();
+        self.next_collection = (self.stats.bytes_allocated * self.config.growth_factor)
+            .max(self.config.initial_threshold);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chunk::{Chunk, FunctionProto},
+        stmt::FunctionDecl,
+        token::{Token, TokenType},
+    };
+
+    fn instance(heap: &mut Heap) -> Rc<RefCell<LoxInstance>> {
+        let class = heap.class(LoxClass::new("C".to_string(), None, HashMap::new()));
+        heap.instance(LoxInstance::new(class))
+    }
+
+    /// A compiled function whose one upvalue holds `value`.
+    fn closure_over(heap: &mut Heap, value: Value) -> Rc<LoxFunction> {
+        let upvalue = heap.upvalue(Upvalue::Closed(value));
+        let proto = FunctionProto {
+            name: "method".to_string(),
+            arity: 0,
+            upvalue_count: 1,
+            chunk: Chunk::default(),
+        };
+        let closure = heap.closure(Closure {
+            function: Rc::new(proto),
+            upvalues: vec![upvalue],
+        });
+        heap.function(LoxFunction::compiled(closure))
+    }
+
+    /// An interpreted function that closed over `environment`.
+    fn function_in(heap: &mut Heap, environment: Rc<RefCell<Environment>>) -> Rc<LoxFunction> {
+        let declaration = FunctionDecl {
+            name: Token::new(TokenType::Identifier, "f", None, 1),
+            params: vec![],
+            body: vec![],
+        };
+        heap.function(LoxFunction::new(
+            Rc::new(declaration),
+            environment,
+            Rc::default(),
+            false,
+        ))
+    }
+
+    #[test]
+    fn collect_frees_an_instance_closure_cycle() {
+        let mut heap = Heap::new();
+        let object = instance(&mut heap);
+        let method = closure_over(&mut heap, Value::Instance(Rc::clone(&object)));
+        object
+            .borrow_mut()
+            .set_field("method".to_string(), Value::Function(method));
+
+        let watch = Rc::downgrade(&object);
+        drop(object);
+        assert!(watch.upgrade().is_some(), "the cycle keeps itself alive");
+
+        let before = heap.stats();
+        heap.collect();
+        let after = heap.stats();
+
+        assert!(watch.upgrade().is_none());
+        // The instance, its class, the upvalue, the closure and the function.
+        assert_eq!(after.objects_freed - before.objects_freed, 5);
+        assert!(after.bytes_freed > before.bytes_freed);
+        assert_eq!(after.bytes_allocated, 0);
+    }
+
+    #[test]
+    fn collect_frees_an_environment_cycle() {
+        let mut heap = Heap::new();
+        let globals = heap.environment(Environment::new());
+        let scope = heap.environment(Environment::with_enclosing(Rc::clone(&globals)));
+        let function = function_in(&mut heap, Rc::clone(&scope));
+        scope
+            .borrow_mut()
+            .define("f".to_string(), Value::Function(function));
+
+        let watch = Rc::downgrade(&scope);
+        drop(scope);
+
+        let before = heap.stats();
+        heap.collect();
+        let after = heap.stats();
+
+        assert!(watch.upgrade().is_none());
+        assert_eq!(after.objects_freed - before.objects_freed, 2);
+        assert!(after.bytes_freed > before.bytes_freed);
+        assert!(
+            globals.try_borrow_mut().is_ok(),
+            "the enclosing scope survives"
+        );
+    }
+
+    #[test]
+    fn collect_keeps_what_is_reachable() {
+        let mut heap = Heap::new();
+
+        // Reachable only through a root the heap can't see.
+        let root = instance(&mut heap);
+        let child = instance(&mut heap);
+        let method = closure_over(&mut heap, Value::Instance(Rc::clone(&child)));
+        root.borrow_mut()
+            .set_field("method".to_string(), Value::Function(method));
+        let watch = Rc::downgrade(&child);
+        drop(child);
+
+        // A cycle whose instance is borrowed by the code running right now,
+        // so the collector can't see what it refers to.
+        let borrowed = instance(&mut heap);
+        let own = closure_over(&mut heap, Value::Instance(Rc::clone(&borrowed)));
+        borrowed
+            .borrow_mut()
+            .set_field("own".to_string(), Value::Function(own));
+        let held = Rc::downgrade(&borrowed);
+        let guard = borrowed.borrow_mut();
+
+        let before = heap.stats();
+        heap.collect();
+        let after = heap.stats();
+
+        assert_eq!(after.objects_freed, before.objects_freed);
+        assert!(watch.upgrade().is_some());
+        assert!(matches!(guard.field("own"), Some(Value::Function(_))));
+        drop(guard);
+        drop(borrowed);
+
+        // Once released and dropped, that cycle is garbage like any other.
+        heap.collect();
+        assert!(held.upgrade().is_none());
+        assert!(watch.upgrade().is_some());
+        assert!(root.borrow().field("method").is_some());
+    }
+}
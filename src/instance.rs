@@ -0,0 +1,93 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::{self, Display},
+    rc::Rc,
+};
+
+use crate::{
+    class::LoxClass,
+    heap::{Heap, Trace},
+    interpreter::{RuntimeError, RuntimeErrorKind},
+    token::Token,
+    value::Value,
+};
+
+pub struct LoxInstance {
+    class: Rc<LoxClass>,
+    fields: HashMap<String, Value>,
+}
+
+impl LoxInstance {
+    pub(crate) fn new(class: Rc<LoxClass>) -> Self {
+        LoxInstance {
+            class,
+            fields: HashMap::new(),
+        }
+    }
+
+    /// Looks up a property, preferring fields over methods. Methods are
+    /// returned bound to `instance` so `this` resolves inside them.
+    pub(crate) fn get(
+        instance: &Rc<RefCell<LoxInstance>>,
+        name: &Token,
+        heap: &mut Heap,
+    ) -> Result<Value, RuntimeError> {
+        if let Some(value) = instance.borrow().fields.get(&name.lexeme) {
+            return Ok(value.clone());
+        }
+
+        let method = instance.borrow().class.find_method(&name.lexeme);
+        match method {
+            Some(method) => Ok(Value::Function(method.bind(Rc::clone(instance), heap))),
+            None => Err(RuntimeError::new(RuntimeErrorKind::UndefinedProperty, name)),
+        }
+    }
+
+    pub(crate) fn set(&mut self, name: &Token, value: Value) {
+        self.set_field(name.lexeme.clone(), value);
+    }
+
+    /// Defines or overwrites a field.
+    pub fn set_field(&mut self, name: String, value: Value) {
+        self.fields.insert(name, value);
+    }
+
+    /// The name of the instance's class.
+    pub fn class_name(&self) -> &str {
+        &self.class.name
+    }
+
+    /// Reads a field without looking at methods.
+    pub fn field(&self, name: &str) -> Option<Value> {
+        self.fields.get(name).cloned()
+    }
+
+    /// Every field with its value, in no particular order.
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.fields
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+    }
+
+    /// Drops every field.
+    pub(crate) fn clear(&mut self) {
+        self.fields.clear();
+    }
+}
+
+impl Trace for LoxInstance {
+    fn trace(&self, visit: &mut dyn FnMut(*const ())) {
+        visit(Rc::as_ptr(&self.class) as *const ());
+
+        for value in self.fields.values() {
+            value.trace(visit);
+        }
+    }
+}
+
+impl Display for LoxInstance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} instance", self.class.name)
+    }
+}
@@ -1,168 +1,815 @@
-use std::{error::Error, fmt::Display};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    error::Error,
+    fmt::Display,
+    io::{self, Write},
+    rc::Rc,
+};
 
 use crate::{
+    class::LoxClass,
     environment::Environment,
-    expr::Expr,
+    expr::{Expr, ExprId},
+    function::LoxFunction,
+    heap::Heap,
+    instance::LoxInstance,
+    limits::{Budget, Limits},
+    rlox::Dialect,
+    stdlib::Capability,
     stmt::Stmt,
-    token::{Literal, TokenType},
+    token::{Span, Token, TokenType},
+    value::{Value, ValueType},
 };
 
+/// Stack space left below which evaluating another expression first moves
+/// to a fresh stack segment of [`STACK_SEGMENT`] bytes.
+const STACK_RED_ZONE: usize = 128 * 1024;
+const STACK_SEGMENT: usize = 2 * 1024 * 1024;
+
+/// Where `print` writes. Shared so that both backends of an [`Rlox`] write to
+/// the same sink.
+///
+/// [`Rlox`]: crate::rlox::Rlox
+pub type Output = Rc<RefCell<dyn Write>>;
+
+/// Scope depth of every resolved local variable reference in a program.
+pub type Locals = HashMap<ExprId, usize>;
+
 pub struct Interpreter {
-    environment: Environment,
+    globals: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    /// Scope depths for the program running now. Functions keep the table of
+    /// the program that declared them, so a table lives only as long as code
+    /// that can still use it.
+    locals: Rc<Locals>,
+    heap: Heap,
+    budget: Budget,
+    /// Lox functions currently running, outermost first.
+    calls: Vec<ActiveCall>,
+    /// Whether the outermost code running is a script rather than a call
+    /// from the host.
+    in_script: bool,
+    dialect: Dialect,
+    output: Output,
+}
+
+/// A call of a Lox function in progress.
+struct ActiveCall {
+    function: String,
+    /// Where the function was called from.
+    call_site: Span,
 }
 
-#[derive(Debug)]
+/// The category of a [`RuntimeError`]. Embedders should match on this rather
+/// than on the rendered message, which may change between releases.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RuntimeErrorKind {
+    /// An operand had the wrong type, e.g. `-"str"`.
+    TypeMismatch {
+        expected: ValueType,
+        actual: ValueType,
+    },
+    /// A binary operator doesn't support this combination of operand types.
+    UnsupportedOperands {
+        left: ValueType,
+        right: ValueType,
+    },
+    /// A function made by one backend was called on the other, e.g. after
+    /// the host passed it between two [`Rlox`](crate::rlox::Rlox) instances.
+    ForeignFunction,
+    /// Strict Lox's error for ordering or arithmetic on anything but two
+    /// numbers, e.g. `"a" < "b"`.
+    OperandsNotNumbers,
+    /// The operator token can't be evaluated in this position.
+    InvalidOperator,
+    UndefinedVariable,
+    UndefinedProperty,
+    DivisionByZero,
+    ArityMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    NotCallable {
+        actual: ValueType,
+    },
+    /// A property was read or written on something other than an instance.
+    NotAnInstance {
+        actual: ValueType,
+    },
+    SuperclassNotClass {
+        actual: ValueType,
+    },
+    /// `print` couldn't write to the output sink.
+    OutputFailed {
+        message: String,
+    },
+    /// A native function was passed an argument of the wrong type.
+    ArgumentType {
+        /// 1-based position of the argument.
+        position: usize,
+        expected: ValueType,
+        actual: ValueType,
+    },
+    /// An error raised by a native function.
+    Native {
+        message: String,
+    },
+    /// The script ran for more steps than [`Limits::max_steps`] allows.
+    StepLimitExceeded,
+    /// Calls nested deeper than [`Limits::max_call_depth`].
+    StackOverflow,
+    /// The script ran for longer than [`Limits::timeout`].
+    Timeout,
+    /// Live objects outgrew [`Limits::max_heap_bytes`].
+    HeapLimitExceeded,
+    /// A native needed a capability the script wasn't granted.
+    PermissionDenied {
+        capability: Capability,
+    },
+}
+
+impl RuntimeErrorKind {
+    /// A stable identifier for this kind of error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            RuntimeErrorKind::TypeMismatch { .. } => "R0001",
+            RuntimeErrorKind::UnsupportedOperands { .. } => "R0002",
+            RuntimeErrorKind::InvalidOperator => "R0003",
+            RuntimeErrorKind::UndefinedVariable => "R0004",
+            RuntimeErrorKind::UndefinedProperty => "R0005",
+            RuntimeErrorKind::DivisionByZero => "R0006",
+            RuntimeErrorKind::ArityMismatch { .. } => "R0007",
+            RuntimeErrorKind::NotCallable { .. } => "R0008",
+            RuntimeErrorKind::NotAnInstance { .. } => "R0009",
+            RuntimeErrorKind::SuperclassNotClass { .. } => "R0010",
+            RuntimeErrorKind::OutputFailed { .. } => "R0011",
+            RuntimeErrorKind::ArgumentType { .. } => "R0012",
+            RuntimeErrorKind::Native { .. } => "R0013",
+            RuntimeErrorKind::StepLimitExceeded => "R0014",
+            RuntimeErrorKind::StackOverflow => "R0015",
+            RuntimeErrorKind::Timeout => "R0016",
+            RuntimeErrorKind::HeapLimitExceeded => "R0017",
+            RuntimeErrorKind::PermissionDenied { .. } => "R0018",
+            RuntimeErrorKind::OperandsNotNumbers => "R0019",
+            RuntimeErrorKind::ForeignFunction => "R0020",
+        }
+    }
+
+    /// An error for a native function to raise with its own message.
+    pub fn native(message: impl Into<String>) -> Self {
+        RuntimeErrorKind::Native {
+            message: message.into(),
+        }
+    }
+
+    pub fn output_failed(error: io::Error) -> Self {
+        RuntimeErrorKind::OutputFailed {
+            message: error.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct RuntimeError {
-    pub message: String,
-    pub line: usize,
+    pub kind: RuntimeErrorKind,
+    /// The token the error is reported at: an operator, a name or a call's
+    /// closing parenthesis.
+    pub token: Box<Token>,
+    /// The calls in progress when the error happened, innermost first.
+    pub backtrace: Vec<StackFrame>,
+}
+
+/// A call in progress when a runtime error happened.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StackFrame {
+    /// The file the code came from, when the runner knows it.
+    pub file: Option<Rc<str>>,
+    /// The function running, or `None` for the top level of the script.
+    pub function: Option<String>,
+    /// How far the function had got: the error itself in the innermost
+    /// frame, and the call to the next frame in the others.
+    pub span: Span,
+}
+
+impl Display for StackFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "[{}:{}]", file, self.span.line)?,
+            None => write!(f, "[line {}]", self.span.line)?,
+        }
+        match &self.function {
+            Some(function) => write!(f, " in {}()", function),
+            None => write!(f, " in script"),
+        }
+    }
+}
+
+impl RuntimeError {
+    pub fn new(kind: RuntimeErrorKind, token: &Token) -> Self {
+        RuntimeError {
+            kind,
+            token: Box::new(token.clone()),
+            backtrace: vec![],
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.kind.code()
+    }
+
+    pub fn line(&self) -> usize {
+        self.token.line
+    }
+
+    pub fn span(&self) -> Span {
+        self.token.span
+    }
+
+    pub fn message(&self) -> String {
+        let lexeme = &self.token.lexeme;
+
+        match &self.kind {
+            RuntimeErrorKind::TypeMismatch { expected, actual } => {
+                format!("Operand must be a {}, got {}.", expected, actual)
+            }
+            RuntimeErrorKind::UnsupportedOperands { left, right } => {
+                format!("Can not perform {} on {} and {}.", lexeme, left, right)
+            }
+            RuntimeErrorKind::OperandsNotNumbers => "Operands must be numbers.".to_string(),
+            RuntimeErrorKind::ForeignFunction => {
+                "Can not call a function that belongs to the other backend.".to_string()
+            }
+            RuntimeErrorKind::InvalidOperator => format!("Invalid operator \"{}\".", lexeme),
+            RuntimeErrorKind::UndefinedVariable => format!("Undefined variable \"{}\".", lexeme),
+            RuntimeErrorKind::UndefinedProperty => format!("Undefined property \"{}\".", lexeme),
+            RuntimeErrorKind::DivisionByZero => "Can not divide by zero.".to_string(),
+            RuntimeErrorKind::ArityMismatch { expected, actual } => {
+                format!("Expected {} arguments but got {}.", expected, actual)
+            }
+            RuntimeErrorKind::NotCallable { actual } => {
+                format!("Can only call functions and classes, got {}.", actual)
+            }
+            RuntimeErrorKind::NotAnInstance { actual } => {
+                format!("Only instances have properties, got {}.", actual)
+            }
+            RuntimeErrorKind::SuperclassNotClass { actual } => {
+                format!("Superclass must be a class, got {}.", actual)
+            }
+            RuntimeErrorKind::OutputFailed { message } => {
+                format!("Could not write output: {}.", message)
+            }
+            RuntimeErrorKind::ArgumentType {
+                position,
+                expected,
+                actual,
+            } => format!(
+                "Argument {} must be a {}, got {}.",
+                position, expected, actual
+            ),
+            RuntimeErrorKind::Native { message } => message.clone(),
+            RuntimeErrorKind::StepLimitExceeded => "Step limit exceeded.".to_string(),
+            RuntimeErrorKind::StackOverflow => "Stack overflow.".to_string(),
+            RuntimeErrorKind::Timeout => "Execution timed out.".to_string(),
+            RuntimeErrorKind::HeapLimitExceeded => "Heap limit exceeded.".to_string(),
+            RuntimeErrorKind::PermissionDenied { capability } => {
+                format!("Permission denied: {} is not allowed.", capability)
+            }
+        }
+    }
 }
 
 impl Display for RuntimeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "[error at line {}] {}", self.line, self.message)
+        write!(
+            f,
+            "[error {} at line {}:{}] {}",
+            self.code(),
+            self.line(),
+            self.span().column,
+            self.message()
+        )
     }
 }
 
 impl Error for RuntimeError {}
 
+/// Non-local exits that unwind through `execute`: a `return` carrying its
+/// value up to the enclosing call, or a runtime error.
+pub enum Unwind {
+    Return(Value),
+    Error(RuntimeError),
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(error: RuntimeError) -> Self {
+        Unwind::Error(error)
+    }
+}
+
 impl Interpreter {
-    pub fn new() -> Self {
+    pub fn new(output: Output) -> Self {
+        let mut heap = Heap::new();
+        let globals = heap.environment(Environment::new());
+
         Interpreter {
-            environment: Environment::new(),
+            environment: Rc::clone(&globals),
+            globals,
+            locals: Rc::default(),
+            heap,
+            budget: Budget::new(),
+            calls: vec![],
+            in_script: false,
+            dialect: Dialect::Extended,
+            output,
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.budget.set_limits(limits);
+    }
+
+    pub fn set_dialect(&mut self, dialect: Dialect) {
+        self.dialect = dialect;
+    }
+
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    pub fn heap_mut(&mut self) -> &mut Heap {
+        &mut self.heap
+    }
+
+    /// Swaps in the scope depths of another program, such as the one a
+    /// function was declared in, and returns those it replaces.
+    pub fn replace_locals(&mut self, locals: Rc<Locals>) -> Rc<Locals> {
+        std::mem::replace(&mut self.locals, locals)
+    }
+
+    fn look_up_variable(&self, name: &Token, id: ExprId) -> Result<Value, RuntimeError> {
+        match self.locals.get(&id) {
+            Some(depth) => Ok(self.environment.borrow().get_at(*depth, &name.lexeme)),
+            None => self.globals.borrow().get(name),
         }
     }
 
-    pub fn interpret(&mut self, statements: Vec<Stmt>) -> Result<(), RuntimeError> {
+    /// Runs `statements`, resolved into `locals`, and returns the script's
+    /// result: the value of a top-level `return`, or `nil`.
+    pub fn interpret(
+        &mut self,
+        statements: Vec<Stmt>,
+        locals: Locals,
+    ) -> Result<Value, RuntimeError> {
+        self.locals = Rc::new(locals);
+        self.budget.start();
+        self.calls.clear();
+        self.in_script = true;
+
         for statement in statements {
-            self.execute(&statement)?;
+            match self.execute(&statement) {
+                Ok(()) => {}
+                Err(Unwind::Error(error)) => return Err(self.with_backtrace(error)),
+                // The resolver rejects top-level `return` in source code, so
+                // this is only reached through `Rlox::eval`.
+                Err(Unwind::Return(value)) => return Ok(value),
+            }
         }
-        Ok(())
+        Ok(Value::Nil)
     }
 
-    fn stringify(&self, value: &Literal) -> String {
-        value.to_string()
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.globals.borrow().lookup(name)
     }
 
-    fn is_truthy(&self, literal: &Literal) -> bool {
-        match literal {
-            Literal::Number(value) => *value != 0.0,
-            Literal::Boolean(value) => *value,
-            Literal::String(_) => true,
-            Literal::Nil => false,
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.globals.borrow_mut().define(name.to_string(), value);
+    }
+
+    /// Calls a function or class from outside any running code and returns
+    /// its result. Errors about the call itself are reported at `paren`.
+    pub fn invoke(
+        &mut self,
+        callee: Value,
+        arguments: Vec<Value>,
+        paren: &Token,
+    ) -> Result<Value, RuntimeError> {
+        self.budget.start();
+        self.calls.clear();
+        self.in_script = false;
+
+        self.call_value(callee, arguments, paren)
+            .map_err(|error| self.with_backtrace(error))
+    }
+
+    fn call_value(
+        &mut self,
+        callee: Value,
+        arguments: Vec<Value>,
+        paren: &Token,
+    ) -> Result<Value, RuntimeError> {
+        let arity = match &callee {
+            Value::Function(function) => function.arity(),
+            Value::Class(class) => class.arity(),
+            value => {
+                return Err(RuntimeError::new(
+                    RuntimeErrorKind::NotCallable {
+                        actual: value.value_type(),
+                    },
+                    paren,
+                ));
+            }
+        };
+
+        if arguments.len() != arity {
+            return Err(RuntimeError::new(
+                RuntimeErrorKind::ArityMismatch {
+                    expected: arity,
+                    actual: arguments.len(),
+                },
+                paren,
+            ));
+        }
+
+        self.budget
+            .enter_call(self.calls.len() + 1)
+            .map_err(|kind| RuntimeError::new(kind, paren))?;
+
+        // Natives, and classes without an initializer, run no Lox code of
+        // their own, so errors in them belong to the caller's frame.
+        let function = match &callee {
+            Value::Function(function) if !function.is_native() => Some(function.name()),
+            Value::Class(class) => class.find_method("init").map(|_| "init"),
+            _ => None,
+        };
+        let call = function.map(|function| ActiveCall {
+            function: function.to_string(),
+            call_site: paren.span,
+        });
+        let in_frame = call.is_some();
+        self.calls.extend(call);
+
+        let mut result = match callee {
+            Value::Function(function) => function.call(self, arguments, paren),
+            Value::Class(class) => class.call(self, arguments, paren),
+            _ => unreachable!("callee was checked to be callable"),
+        };
+
+        if in_frame {
+            result = result.map_err(|error| self.with_backtrace(error));
+            self.calls.pop();
         }
+
+        result
+    }
+
+    /// Records the calls in progress on `error`, unless a frame nested more
+    /// deeply already has.
+    fn with_backtrace(&self, mut error: RuntimeError) -> RuntimeError {
+        if !error.backtrace.is_empty() {
+            return error;
+        }
+
+        let mut span = error.span();
+        for call in self.calls.iter().rev() {
+            error.backtrace.push(StackFrame {
+                file: None,
+                function: Some(call.function.clone()),
+                span,
+            });
+            span = call.call_site;
+        }
+        if self.in_script {
+            error.backtrace.push(StackFrame {
+                file: None,
+                function: None,
+                span,
+            });
+        }
+
+        error
+    }
+
+    fn stringify(&self, value: &Value) -> String {
+        value.to_string()
     }
 
-    fn execute(&mut self, stmt: &Stmt) -> Result<(), RuntimeError> {
+    fn execute(&mut self, stmt: &Stmt) -> Result<(), Unwind> {
+        self.budget.step(&mut self.heap).map_err(|kind| {
+            let span = stmt.span();
+            let token = Token::new(TokenType::Identifier, "", None, span.line).with_span(span);
+            RuntimeError::new(kind, &token)
+        })?;
+
         match stmt {
-            Stmt::Expression { expression } => {
-                let value = self.evaluate(expression)?;
+            Stmt::Expression { expression, .. } => {
+                self.evaluate(expression)?;
                 Ok(())
             }
-            Stmt::Print { expression } => {
+            Stmt::Print { expression, span } => {
                 let value = self.evaluate(expression)?;
-                println!("{}", self.stringify(&value));
+                writeln!(self.output.borrow_mut(), "{}", self.stringify(&value)).map_err(
+                    |error| {
+                        let keyword =
+                            Token::new(TokenType::Print, "print".to_string(), None, span.line)
+                                .with_span(*span);
+                        RuntimeError::new(RuntimeErrorKind::output_failed(error), &keyword)
+                    },
+                )?;
                 Ok(())
             }
-            Stmt::Var { name, initializer } => {
+            Stmt::Var {
+                name, initializer, ..
+            } => {
                 let value = self.evaluate(initializer)?;
                 // println!("{} = {}", name.lexeme, self.stringify(&value));
-                self.environment.define(name.lexeme.clone(), value)?;
+                self.environment
+                    .borrow_mut()
+                    .define(name.lexeme.clone(), value);
+                Ok(())
+            }
+            Stmt::Block { statements, .. } => {
+                let environment = self
+                    .heap
+                    .environment(Environment::with_enclosing(Rc::clone(&self.environment)));
+                self.execute_block(statements, environment)
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let value = self.evaluate(condition)?;
+                if value.is_truthy(self.dialect) {
+                    self.execute(then_branch)
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(else_branch)
+                } else {
+                    Ok(())
+                }
+            }
+            Stmt::While {
+                condition, body, ..
+            } => {
+                loop {
+                    let value = self.evaluate(condition)?;
+                    if !value.is_truthy(self.dialect) {
+                        break;
+                    }
+                    self.execute(body)?;
+                }
                 Ok(())
             }
+            Stmt::Function { declaration, .. } => {
+                let function = LoxFunction::new(
+                    Rc::clone(declaration),
+                    Rc::clone(&self.environment),
+                    Rc::clone(&self.locals),
+                    false,
+                );
+                let function = self.heap.function(function);
+                self.environment
+                    .borrow_mut()
+                    .define(declaration.name.lexeme.clone(), Value::Function(function));
+                Ok(())
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+                ..
+            } => {
+                let superclass = match superclass {
+                    Some(superclass) => match self.evaluate(superclass)? {
+                        Value::Class(class) => Some(class),
+                        value => {
+                            let token = match superclass.as_ref() {
+                                Expr::Variable { name, .. } => name,
+                                _ => name,
+                            };
+                            return Err(RuntimeError::new(
+                                RuntimeErrorKind::SuperclassNotClass {
+                                    actual: value.value_type(),
+                                },
+                                token,
+                            )
+                            .into());
+                        }
+                    },
+                    None => None,
+                };
+
+                self.environment
+                    .borrow_mut()
+                    .define(name.lexeme.clone(), Value::Nil);
+
+                // Methods of a subclass close over an extra scope holding `super`.
+                let enclosing = Rc::clone(&self.environment);
+                if let Some(superclass) = &superclass {
+                    let mut environment = Environment::with_enclosing(Rc::clone(&enclosing));
+                    environment.define("super".to_string(), Value::Class(Rc::clone(superclass)));
+                    self.environment = self.heap.environment(environment);
+                }
+
+                let methods = methods
+                    .iter()
+                    .map(|method| {
+                        let function = LoxFunction::new(
+                            Rc::clone(method),
+                            Rc::clone(&self.environment),
+                            Rc::clone(&self.locals),
+                            method.name.lexeme == "init",
+                        );
+                        (method.name.lexeme.clone(), self.heap.function(function))
+                    })
+                    .collect();
+
+                self.environment = enclosing;
+
+                let class = LoxClass::new(name.lexeme.clone(), superclass, methods);
+                self.environment
+                    .borrow_mut()
+                    .assign(name, Value::Class(self.heap.class(class)))?;
+                Ok(())
+            }
+            Stmt::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => self.evaluate(value)?,
+                    None => Value::Nil,
+                };
+                Err(Unwind::Return(value))
+            }
         }
     }
 
-    fn evaluate(&self, expr: &Expr) -> Result<Literal, RuntimeError> {
+    pub fn execute_block(
+        &mut self,
+        statements: &[Stmt],
+        environment: Rc<RefCell<Environment>>,
+    ) -> Result<(), Unwind> {
+        let previous = std::mem::replace(&mut self.environment, environment);
+
+        let result = statements
+            .iter()
+            .try_for_each(|statement| self.execute(statement));
+
+        self.environment = previous;
+
+        result
+    }
+
+    /// Evaluates `expr`. Lox calls recurse through here on the Rust stack, so
+    /// it grows onto the heap as needed and only [`Limits::max_call_depth`]
+    /// bounds recursion, however small the host thread's stack is.
+    fn evaluate(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, || {
+            self.evaluate_expression(expr)
+        })
+    }
+
+    fn evaluate_expression(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
         match expr {
-            Expr::Literal { value } => Ok(value.clone()),
-            Expr::Grouping { expression } => self.evaluate(&expression),
-            Expr::Unary { operator, right } => {
-                let value = self.evaluate(&right)?;
-                match operator.token_type {
-                    TokenType::Minus => {
-                        let value = value.as_number();
-                        match value {
-                            Some(v) => Ok(Literal::Number(-v)),
-                            None => {
-                                panic!("Operand must be a number")
-                            }
-                        }
-                    }
-                    TokenType::Bang => Ok(Literal::Boolean(!self.is_truthy(&value))),
-                    _ => {
-                        eprintln!("Invalid operator for an unary expression");
-                        Err(RuntimeError {
-                            message: "Invalid operator for an unary expression".to_string(),
-                            line: operator.line,
-                        })
-                    }
-                }
+            Expr::Literal { value, .. } => Ok(Value::from(value.clone())),
+            Expr::Grouping { expression, .. } => self.evaluate(expression),
+            Expr::Unary {
+                operator, right, ..
+            } => {
+                let value = self.evaluate(right)?;
+                value
+                    .unary(&operator.token_type, self.dialect)
+                    .map_err(|kind| RuntimeError::new(kind, operator))
             }
             Expr::Binary {
                 left,
                 operator,
                 right,
+                ..
             } => {
-                let left_value = self.evaluate(&left)?;
-                let right_value = self.evaluate(&right)?;
-
-                Ok(match (left_value, right_value) {
-                    (Literal::Number(l), Literal::Number(r)) => match operator.token_type {
-                        TokenType::GreaterEqual => Literal::Boolean(l >= r),
-                        TokenType::Greater => Literal::Boolean(l > r),
-                        TokenType::Less => Literal::Boolean(l < r),
-                        TokenType::LessEqual => Literal::Boolean(l <= r),
-                        TokenType::EqualEqual => Literal::Boolean(l == r),
-                        TokenType::BangEqual => Literal::Boolean(l != r),
-                        TokenType::Plus => Literal::Number(l + r),
-                        TokenType::Minus => Literal::Number(l - r),
-                        TokenType::Star => Literal::Number(l * r),
-                        TokenType::Slash => {
-                            if r == 0.0 {
-                                return Err(RuntimeError {
-                                    line: operator.line,
-                                    message: "Can not divide by zero".to_string(),
-                                });
-                            }
-
-                            Literal::Number(l / r)
-                        }
+                let left_value = self.evaluate(left)?;
+                let right_value = self.evaluate(right)?;
 
-                        _ => {
-                            return Err(RuntimeError {
-                                line: operator.line,
-                                message: "Invalid operator for a binary expression".to_string(),
-                            });
-                        }
-                    },
-                    (Literal::String(l), r) => Literal::String(l.to_string() + &r.to_string()),
-                    (l, Literal::String(r)) => Literal::String(l.to_string() + &r.to_string()),
-                    _ => {
-                        return Err(RuntimeError {
-                            line: operator.line,
-                            message: format!(
-                                "Can not perform {} on this expression",
-                                operator.lexeme
-                            ),
-                        });
-                    }
-                })
+                let value = left_value
+                    .binary(&operator.token_type, right_value, self.dialect)
+                    .map_err(|kind| RuntimeError::new(kind, operator))?;
+                self.heap.string(&value);
+                Ok(value)
             }
             Expr::Ternary {
                 condition,
                 positive,
                 negative,
+                ..
             } => {
-                let value = self.evaluate(&condition)?;
-                if self.is_truthy(&value) {
+                let value = self.evaluate(condition)?;
+                if value.is_truthy(self.dialect) {
                     self.evaluate(positive)
                 } else {
                     self.evaluate(negative)
                 }
             }
-            Expr::Variable { name } => {
-                dbg!(name);
-                self.environment.get(name).cloned()
+            Expr::Logical {
+                left,
+                operator,
+                right,
+                ..
+            } => {
+                let left_value = self.evaluate(left)?;
+                let short_circuits = match operator.token_type {
+                    TokenType::Or => left_value.is_truthy(self.dialect),
+                    _ => !left_value.is_truthy(self.dialect),
+                };
+
+                if short_circuits {
+                    Ok(left_value)
+                } else {
+                    self.evaluate(right)
+                }
+            }
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+                ..
+            } => {
+                let callee = self.evaluate(callee)?;
+
+                let mut values = Vec::with_capacity(arguments.len());
+                for argument in arguments {
+                    values.push(self.evaluate(argument)?);
+                }
+
+                self.call_value(callee, values, paren)
+            }
+            Expr::Get { object, name, .. } => match self.evaluate(object)? {
+                Value::Instance(instance) => LoxInstance::get(&instance, name, &mut self.heap),
+                value => Err(RuntimeError::new(
+                    RuntimeErrorKind::NotAnInstance {
+                        actual: value.value_type(),
+                    },
+                    name,
+                )),
+            },
+            Expr::Set {
+                object,
+                name,
+                value,
+                ..
+            } => {
+                // Both operands are evaluated before the check, as the VM does.
+                let object = self.evaluate(object)?;
+                let value = self.evaluate(value)?;
+
+                let Value::Instance(instance) = object else {
+                    return Err(RuntimeError::new(
+                        RuntimeErrorKind::NotAnInstance {
+                            actual: object.value_type(),
+                        },
+                        name,
+                    ));
+                };
+
+                instance.borrow_mut().set(name, value.clone());
+                Ok(value)
+            }
+            Expr::This { keyword, id, .. } => self.look_up_variable(keyword, *id),
+            Expr::Super { method, id, .. } => {
+                let depth = self.locals[id];
+
+                // `this` is bound in the scope just inside the one holding `super`.
+                let Value::Class(superclass) = self.environment.borrow().get_at(depth, "super")
+                else {
+                    unreachable!("\"super\" is always bound to a class");
+                };
+                let Value::Instance(instance) = self.environment.borrow().get_at(depth - 1, "this")
+                else {
+                    unreachable!("\"this\" is always bound to an instance");
+                };
+
+                match superclass.find_method(&method.lexeme) {
+                    Some(function) => Ok(Value::Function(function.bind(instance, &mut self.heap))),
+                    None => Err(RuntimeError::new(
+                        RuntimeErrorKind::UndefinedProperty,
+                        method,
+                    )),
+                }
+            }
+            Expr::Variable { name, id, .. } => self.look_up_variable(name, *id),
+            Expr::Assign {
+                name, value, id, ..
+            } => {
+                let value = self.evaluate(value)?;
+
+                match self.locals.get(id) {
+                    Some(depth) => {
+                        self.environment
+                            .borrow_mut()
+                            .assign_at(*depth, name, value.clone());
+                    }
+                    None => self.globals.borrow_mut().assign(name, value.clone())?,
+                }
+
+                Ok(value)
             }
         }
     }
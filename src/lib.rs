@@ -1,3 +1,64 @@
+//! A Lox interpreter that can be embedded as a scripting layer.
+//!
+//! [`Rlox`] is the entry point: it scans, parses and resolves source code and
+//! runs it on the tree-walking interpreter or the bytecode VM.
+//!
+//! ```
+//! use rlox::{Backend, Rlox, Value};
+//!
+//! let mut rlox = Rlox::with_backend(Backend::TreeWalker);
+//! rlox.set_global("base", Value::Number(40.0));
+//!
+//! let value = rlox.eval("fn add(a, b) { return a + b; } add(base, 2);").unwrap();
+//! assert_eq!(value, Value::Number(42.0));
+//! ```
+//!
+//! The tokens and syntax tree are public too, through [`Rlox::tokens`] and
+//! [`Rlox::parse`], for tools that work on Lox source without running it.
+
 pub mod ast_printer;
+pub(crate) mod chunk;
+pub(crate) mod class;
+pub(crate) mod compiler;
+#[cfg(feature = "serde")]
+pub(crate) mod convert;
+pub(crate) mod diagnostics;
+pub(crate) mod disassembler;
+pub(crate) mod environment;
 pub mod expr;
+pub(crate) mod function;
+pub(crate) mod heap;
+pub(crate) mod instance;
+pub(crate) mod interpreter;
+pub(crate) mod limits;
+pub(crate) mod native;
+pub(crate) mod parser;
+pub(crate) mod resolver;
+pub(crate) mod rlox;
+pub(crate) mod rloxc;
+pub(crate) mod scanner;
+pub(crate) mod stdlib;
+pub mod stmt;
 pub mod token;
+pub(crate) mod value;
+pub(crate) mod vm;
+
+pub use crate::{
+    class::LoxClass,
+    compiler::CompileError,
+    function::LoxFunction,
+    heap::{GcConfig, GcStats},
+    instance::LoxInstance,
+    interpreter::{RuntimeError, RuntimeErrorKind, StackFrame},
+    limits::Limits,
+    parser::ParseError,
+    resolver::ResolveError,
+    rlox::{Backend, Dialect, Repl, Rlox, RloxError},
+    rloxc::{LoadError, Module},
+    scanner::ScanError,
+    stdlib::{Capabilities, Capability},
+    value::{Value, ValueType},
+};
+
+#[cfg(feature = "serde")]
+pub use crate::convert::{Error as ConvertError, from_value, to_value};
@@ -0,0 +1,107 @@
+use std::time::{Duration, Instant};
+
+use crate::{heap::Heap, interpreter::RuntimeErrorKind};
+
+/// How often, in steps, the clock is read to enforce [`Limits::timeout`].
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// Bounds on the resources a script may use. Running out of any of them
+/// stops the script with its own kind of [`RuntimeErrorKind`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Statements executed by the tree-walker, or instructions executed by
+    /// the VM.
+    pub max_steps: Option<u64>,
+    /// Calls of Lox functions and classes in progress at once. The
+    /// tree-walker recurses for each of them on a stack that grows as needed,
+    /// so this is bounded by default to keep runaway recursion from using up
+    /// memory.
+    pub max_call_depth: usize,
+    /// Wall-clock time a script may run for.
+    pub timeout: Option<Duration>,
+    /// Estimated size of the live objects tracked by the heap, including the
+    /// strings the script builds.
+    pub max_heap_bytes: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_steps: None,
+            max_call_depth: 256,
+            timeout: None,
+            max_heap_bytes: None,
+        }
+    }
+}
+
+/// The resources used so far by one run of a script.
+#[derive(Debug)]
+pub struct Budget {
+    limits: Limits,
+    steps: u64,
+    started: Instant,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Budget {
+    pub fn new() -> Self {
+        Budget {
+            limits: Limits::default(),
+            steps: 0,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Starts a new run with nothing used yet.
+    pub fn start(&mut self) {
+        self.steps = 0;
+        self.started = Instant::now();
+    }
+
+    /// Checks that a call may start, where `depth` is the number of calls in
+    /// progress once it has, itself included.
+    pub fn enter_call(&self, depth: usize) -> Result<(), RuntimeErrorKind> {
+        if depth > self.limits.max_call_depth {
+            return Err(RuntimeErrorKind::StackOverflow);
+        }
+        Ok(())
+    }
+
+    /// Accounts for one more step, collecting garbage first if the heap has
+    /// outgrown its limit.
+    pub fn step(&mut self, heap: &mut Heap) -> Result<(), RuntimeErrorKind> {
+        self.steps += 1;
+
+        if self.limits.max_steps.is_some_and(|max| self.steps > max) {
+            return Err(RuntimeErrorKind::StepLimitExceeded);
+        }
+
+        if let Some(timeout) = self.limits.timeout
+            && self.steps.is_multiple_of(CLOCK_INTERVAL)
+            && self.started.elapsed() > timeout
+        {
+            return Err(RuntimeErrorKind::Timeout);
+        }
+
+        if let Some(max) = self.limits.max_heap_bytes
+            && heap.stats().bytes_allocated > max
+        {
+            heap.collect();
+            if heap.stats().bytes_allocated > max {
+                return Err(RuntimeErrorKind::HeapLimitExceeded);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,56 +1,193 @@
-use std::{
-    env::args,
-    fs,
-    io::{self},
-};
-
-use crate::rlox::Repl;
-
-mod ast_printer;
-mod environment;
-mod expr;
-mod interpreter;
-mod parser;
-mod rlox;
-mod scanner;
-mod stmt;
-mod token;
-
-fn get_source_code(filename: &str) -> Result<String, io::Error> {
-    let contents = fs::read_to_string(filename)?;
-    Ok(contents)
+use std::{env::args, fs, io, process::ExitCode};
+
+use rlox::{Backend, Capabilities, Dialect, GcConfig, Module, Repl, Rlox, RloxError};
+
+const USAGE: &str = "Usage: rlox [run|check|disasm|compile] [--format=human|json] \
+                     [--backend=tree-walker|vm] [--dialect=strict|extended] \
+                     [--gc-stress] [--gc-stats] [--allow-read=DIR] [--allow-write=DIR] \
+                     [--allow-env] [--allow-process] [-o out.rloxc] [name.rlox|name.rloxc]";
+
+/// Exit statuses follow the BSD `sysexits.h` conventions.
+const EXIT_USAGE: u8 = 64;
+const EXIT_DATA_ERROR: u8 = 65;
+const EXIT_NO_INPUT: u8 = 66;
+const EXIT_SOFTWARE: u8 = 70;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Command {
+    Run,
+    Check,
+    Disasm,
+    Compile,
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = args();
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Human,
+    Json,
+}
+
+struct Options {
+    command: Command,
+    format: Format,
+    backend: Backend,
+    dialect: Dialect,
+    gc: GcConfig,
+    /// Print collector statistics to stderr once the program has run.
+    gc_stats: bool,
+    /// What scripts may do beyond computing and printing.
+    capabilities: Capabilities,
+    file: Option<String>,
+    output: Option<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        command: Command::Run,
+        format: Format::Human,
+        backend: Backend::TreeWalker,
+        dialect: Dialect::Extended,
+        gc: GcConfig::default(),
+        gc_stats: false,
+        capabilities: Capabilities::default(),
+        file: None,
+        output: None,
+    };
+    let mut positional = 0;
+
+    while let Some(arg) = args.next() {
+        if arg == "-o" {
+            let output = args.next().ok_or("Missing file name after -o")?;
+            options.output = Some(output);
+            continue;
+        }
 
-    if args.len() > 2 {
-        println!("Usage: rlox [name.rlox]");
-        return Ok(());
+        if let Some(format) = arg.strip_prefix("--format=") {
+            options.format = match format {
+                "human" => Format::Human,
+                "json" => Format::Json,
+                _ => return Err(format!("Unknown format: {}", format)),
+            };
+            continue;
+        }
+
+        if let Some(backend) = arg.strip_prefix("--backend=") {
+            options.backend = match backend {
+                "tree-walker" => Backend::TreeWalker,
+                "vm" => Backend::Vm,
+                _ => return Err(format!("Unknown backend: {}", backend)),
+            };
+            continue;
+        }
+
+        if let Some(dialect) = arg.strip_prefix("--dialect=") {
+            options.dialect = match dialect {
+                "strict" => Dialect::Strict,
+                "extended" => Dialect::Extended,
+                _ => return Err(format!("Unknown dialect: {}", dialect)),
+            };
+            continue;
+        }
+
+        if arg == "--gc-stress" {
+            options.gc.stress = true;
+            continue;
+        }
+
+        if arg == "--gc-stats" {
+            options.gc_stats = true;
+            continue;
+        }
+
+        if let Some(directory) = arg.strip_prefix("--allow-read=") {
+            options.capabilities.fs_read = Some(directory.into());
+            continue;
+        }
+
+        if let Some(directory) = arg.strip_prefix("--allow-write=") {
+            options.capabilities.fs_write = Some(directory.into());
+            continue;
+        }
+
+        if arg == "--allow-env" {
+            options.capabilities.env = true;
+            continue;
+        }
+
+        if arg == "--allow-process" {
+            options.capabilities.process = true;
+            continue;
+        }
+
+        if arg.starts_with("--") {
+            return Err(format!("Unknown option: {}", arg));
+        }
+
+        match (positional, arg.as_str()) {
+            (0, "run") => options.command = Command::Run,
+            (0, "check") => options.command = Command::Check,
+            (0, "disasm") => options.command = Command::Disasm,
+            (0, "compile") => options.command = Command::Compile,
+            _ if options.file.is_none() => options.file = Some(arg),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+        positional += 1;
+    }
+
+    if options.output.is_some() && options.command != Command::Compile {
+        return Err("-o is only valid with compile".to_string());
     }
 
-    let mut rlox = rlox::Rlox::new();
+    Ok(options)
+}
+
+fn main() -> Result<ExitCode, Box<dyn std::error::Error>> {
+    let options = match parse_args(args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            eprintln!("{}", USAGE);
+            return Ok(ExitCode::from(EXIT_USAGE));
+        }
+    };
+
+    let mut rlox = Rlox::with_backend(options.backend);
+    rlox.set_dialect(options.dialect);
+    rlox.set_gc_config(options.gc);
+    rlox.set_capabilities(options.capabilities.clone());
+
+    let Some(source_code_filename) = options.file.clone() else {
+        if options.command != Command::Run {
+            eprintln!("{}", USAGE);
+            return Ok(ExitCode::from(EXIT_USAGE));
+        }
 
-    if args.len() == 1 {
         let mut repl = Repl::new(rlox);
         repl.run();
-        return Ok(());
+        return Ok(ExitCode::SUCCESS);
+    };
+
+    let is_compiled = source_code_filename.ends_with(".rloxc");
+    if !is_compiled && !source_code_filename.ends_with(".rlox") {
+        eprintln!("Error: Invalid file extension. Expected .rlox or .rloxc");
+        return Ok(ExitCode::from(EXIT_USAGE));
     }
 
-    let source_code_filename = args.into_iter().nth(1).unwrap();
-    if !source_code_filename.ends_with(".rlox") {
-        eprintln!("Error: Invalid file extension. Expected .rlox");
-        return Ok(());
+    if is_compiled && matches!(options.command, Command::Check | Command::Compile) {
+        eprintln!("Error: Expected a .rlox source file");
+        return Ok(ExitCode::from(EXIT_USAGE));
     }
 
-    println!("Source code filename: {}", source_code_filename);
+    if options.command == Command::Run && options.format == Format::Human {
+        println!("Source code filename: {}", source_code_filename);
+    }
 
-    let contents = match get_source_code(source_code_filename.as_str()) {
+    let bytes = match fs::read(&source_code_filename) {
         Ok(v) => v,
         Err(e) => {
             if e.kind() == std::io::ErrorKind::NotFound {
                 eprintln!("File {} not found", source_code_filename.clone());
-                return Ok(());
+                return Ok(ExitCode::from(EXIT_NO_INPUT));
             } else {
                 eprintln!("Error reading file: {}", e);
                 return Err(e.into());
@@ -58,11 +195,120 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
-    if let Err(errors) = rlox.run(contents.as_str()) {
-        for error in errors {
-            eprintln!("{}", error);
+    if is_compiled {
+        let module = match Module::from_bytes(&bytes) {
+            Ok(module) => module,
+            Err(error) => {
+                eprintln!("Error: {}: {}", source_code_filename, error);
+                return Ok(ExitCode::from(EXIT_DATA_ERROR));
+            }
+        };
+
+        let result = match options.command {
+            Command::Disasm => {
+                print!("{}", module.disassemble());
+                Ok(())
+            }
+            _ => rlox.run_compiled(&module),
+        };
+
+        if options.gc_stats {
+            print_gc_stats(&mut rlox)?;
+        }
+
+        // The source isn't at hand, so diagnostics show locations only.
+        return Ok(report(
+            &mut rlox,
+            result,
+            "",
+            &module.source_name,
+            &options,
+        )?);
+    }
+
+    let contents = match String::from_utf8(bytes) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Error reading file: {}", e);
+            return Err(e.into());
+        }
+    };
+
+    rlox.set_source_name(&source_code_filename);
+    let result = match options.command {
+        Command::Run => rlox.run(&contents),
+        Command::Check => rlox.check(&contents),
+        Command::Disasm => rlox
+            .compile(&contents, &source_code_filename)
+            .map(|module| print!("{}", module.disassemble())),
+        Command::Compile => match rlox.compile(&contents, &source_code_filename) {
+            Ok(module) => {
+                let output = options
+                    .output
+                    .clone()
+                    .unwrap_or_else(|| format!("{}c", source_code_filename));
+                fs::write(output, module.to_bytes())?;
+                Ok(())
+            }
+            Err(errors) => Err(errors),
+        },
+    };
+
+    if options.gc_stats {
+        print_gc_stats(&mut rlox)?;
+    }
+
+    Ok(report(
+        &mut rlox,
+        result,
+        &contents,
+        &source_code_filename,
+        &options,
+    )?)
+}
+
+fn print_gc_stats(rlox: &mut Rlox) -> io::Result<()> {
+    let stats = rlox.gc_stats();
+    writeln!(
+        rlox.diagnostics(),
+        "gc: {} collections, {} objects ({} bytes) freed, {} bytes live",
+        stats.collections,
+        stats.objects_freed,
+        stats.bytes_freed,
+        stats.bytes_allocated
+    )
+}
+
+/// Prints the errors in `result`, if any, and picks the exit status.
+fn report(
+    rlox: &mut Rlox,
+    result: Result<(), Vec<RloxError>>,
+    source: &str,
+    file_name: &str,
+    options: &Options,
+) -> io::Result<ExitCode> {
+    let Err(errors) = result else {
+        return Ok(ExitCode::SUCCESS);
+    };
+
+    match options.format {
+        Format::Human => rlox.report_errors(&errors, source, file_name)?,
+        // Like human-readable ones, JSON diagnostics always go to stderr, so
+        // stdout only ever holds the program's output or listing.
+        Format::Json => {
+            for error in &errors {
+                writeln!(rlox.diagnostics(), "{}", error.to_json(file_name))?;
+            }
         }
     }
 
-    Ok(())
+    let runtime_failure = errors
+        .iter()
+        .any(|error| matches!(error, RloxError::Runtime(_)));
+
+    Ok(ExitCode::from(if runtime_failure {
+        EXIT_SOFTWARE
+    } else {
+        EXIT_DATA_ERROR
+    }))
 }
@@ -0,0 +1,114 @@
+use std::rc::Rc;
+
+use crate::{
+    interpreter::RuntimeErrorKind,
+    value::{Value, ValueType},
+};
+
+/// A function implemented by the host. It receives arguments already checked
+/// against its arity; errors are reported at the call's closing parenthesis.
+pub type NativeFn = Rc<dyn Fn(&[Value]) -> Result<Value, RuntimeErrorKind>>;
+
+/// Converts a Lox value into an argument of a typed native function.
+pub trait FromValue: Sized {
+    /// Converts `value`, or returns the type that was expected instead.
+    fn from_value(value: &Value) -> Result<Self, ValueType>;
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Result<Self, ValueType> {
+        Ok(value.clone())
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<Self, ValueType> {
+        match value {
+            Value::Number(number) => Ok(*number),
+            _ => Err(ValueType::Number),
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Result<Self, ValueType> {
+        match value {
+            Value::Boolean(boolean) => Ok(*boolean),
+            _ => Err(ValueType::Boolean),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Result<Self, ValueType> {
+        match value {
+            Value::String(string) => Ok(string.to_string()),
+            _ => Err(ValueType::String),
+        }
+    }
+}
+
+/// The result of a typed native function: a value, or a `Result` carrying
+/// one.
+pub trait IntoNativeResult {
+    fn into_native_result(self) -> Result<Value, RuntimeErrorKind>;
+}
+
+impl<T: Into<Value>> IntoNativeResult for T {
+    fn into_native_result(self) -> Result<Value, RuntimeErrorKind> {
+        Ok(self.into())
+    }
+}
+
+impl<T: Into<Value>> IntoNativeResult for Result<T, RuntimeErrorKind> {
+    fn into_native_result(self) -> Result<Value, RuntimeErrorKind> {
+        self.map(Into::into)
+    }
+}
+
+/// A Rust closure whose parameters and result convert to and from Lox
+/// values, so it can be registered as a native function. `Args` is the tuple
+/// of parameter types; it is inferred from the closure.
+pub trait IntoNative<Args> {
+    fn arity(&self) -> usize;
+
+    fn into_native(self) -> NativeFn;
+}
+
+/// Converts the argument at `index`, reporting a type error if it has the
+/// wrong type.
+fn argument<T: FromValue>(arguments: &[Value], index: usize) -> Result<T, RuntimeErrorKind> {
+    T::from_value(&arguments[index]).map_err(|expected| RuntimeErrorKind::ArgumentType {
+        position: index + 1,
+        expected,
+        actual: arguments[index].value_type(),
+    })
+}
+
+macro_rules! impl_into_native {
+    ($arity:literal $(, $arg:ident: $index:tt)*) => {
+        impl<F, R, $($arg),*> IntoNative<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: IntoNativeResult,
+            $($arg: FromValue,)*
+        {
+            fn arity(&self) -> usize {
+                $arity
+            }
+
+            #[allow(unused_variables)]
+            fn into_native(self) -> NativeFn {
+                Rc::new(move |arguments: &[Value]| {
+                    self($(argument::<$arg>(arguments, $index)?),*).into_native_result()
+                })
+            }
+        }
+    };
+}
+
+impl_into_native!(0);
+impl_into_native!(1, A: 0);
+impl_into_native!(2, A: 0, B: 1);
+impl_into_native!(3, A: 0, B: 1, C: 2);
+impl_into_native!(4, A: 0, B: 1, C: 2, D: 3);
@@ -1,44 +1,57 @@
-use std::{error::Error, fmt::Display};
+use std::{error::Error, fmt::Display, rc::Rc};
 
 use crate::{
-    expr::Expr,
-    stmt::Stmt,
-    token::{Literal, Token, TokenType},
+    expr::{Expr, ExprId},
+    rlox::Dialect,
+    stmt::{FunctionDecl, Stmt},
+    token::{Literal, Span, Token, TokenType},
 };
 
 #[derive(Clone, Debug)]
 pub struct ParseError {
+    pub code: &'static str,
     pub line: usize,
     pub message: String,
+    pub span: Span,
 }
 
+const MAX_ARGUMENTS: usize = 255;
+
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
     errors: Vec<ParseError>,
+    dialect: Dialect,
 }
 
 impl Display for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "[line {}] {}", self.line, self.message)
+        write!(
+            f,
+            "[line {}:{}] {}",
+            self.line, self.span.column, self.message
+        )
     }
 }
 
 impl Error for ParseError {}
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
+    pub fn new(tokens: Vec<Token>, dialect: Dialect) -> Self {
         Parser {
             tokens,
             current: 0,
             errors: vec![],
+            dialect,
         }
     }
 
-    fn report_error<T: Into<String>>(&mut self, message: T, token: Token) {
+    fn report_error<T: Into<String>>(&mut self, code: &'static str, message: T, token: Token) {
         self.errors.push(ParseError {
+            code,
             message: message.into(),
             line: token.line,
+            span: token.span,
         });
     }
 
@@ -55,7 +68,7 @@ impl Parser {
                 }
             }
         }
-        if self.errors.len() != 0 {
+        if !self.errors.is_empty() {
             return Err(self.errors.clone());
         }
 
@@ -63,6 +76,19 @@ impl Parser {
     }
 
     fn declaration(&mut self) -> Result<Stmt, ParseError> {
+        if self.matches(&[TokenType::Class]) {
+            return self.class_declaration();
+        }
+
+        if self.matches(&[TokenType::Fn]) {
+            let start = self.previous_span();
+            let declaration = self.function()?;
+            return Ok(Stmt::Function {
+                declaration,
+                span: self.span_from(start),
+            });
+        }
+
         if self.matches(&[TokenType::Var]) {
             return self.var_declaration();
         }
@@ -70,10 +96,75 @@ impl Parser {
         self.statement()
     }
 
+    fn class_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous_span();
+        let name = self.consume_if(TokenType::Identifier)?;
+
+        let superclass = if self.matches(&[TokenType::Less]) {
+            let name = self.consume_if(TokenType::Identifier)?;
+            Some(Box::new(Expr::Variable {
+                span: name.span,
+                name,
+                id: ExprId::next(),
+            }))
+        } else {
+            None
+        };
+
+        self.consume_if(TokenType::LeftBrace)?;
+
+        let mut methods = vec![];
+        while self.peek().token_type != TokenType::RightBrace && !self.is_at_end() {
+            methods.push(self.function()?);
+        }
+
+        self.consume_if(TokenType::RightBrace)?;
+
+        Ok(Stmt::Class {
+            name,
+            superclass,
+            methods,
+            span: self.span_from(start),
+        })
+    }
+
+    fn function(&mut self) -> Result<Rc<FunctionDecl>, ParseError> {
+        let name = self.consume_if(TokenType::Identifier)?;
+        self.consume_if(TokenType::LeftParen)?;
+
+        let mut params = vec![];
+        if self.peek().token_type != TokenType::RightParen {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    let token = self.peek().clone();
+                    self.report_error(
+                        "P0006",
+                        format!("Can't have more than {} parameters.", MAX_ARGUMENTS),
+                        token,
+                    );
+                }
+
+                params.push(self.consume_if(TokenType::Identifier)?);
+
+                if !self.matches(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
+        self.consume_if(TokenType::RightParen)?;
+
+        self.consume_if(TokenType::LeftBrace)?;
+        let body = self.block()?;
+
+        Ok(Rc::new(FunctionDecl { name, params, body }))
+    }
+
     fn var_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous_span();
         let token = self.consume_if(TokenType::Identifier)?;
         let mut expression = Expr::Literal {
             value: Literal::Nil,
+            span: token.span,
         };
 
         if self.matches(&[TokenType::Equal]) {
@@ -85,16 +176,42 @@ impl Parser {
         let stmt = Stmt::Var {
             name: token,
             initializer: Box::new(expression),
+            span: self.span_from(start),
         };
 
         Ok(stmt)
     }
 
     fn statement(&mut self) -> Result<Stmt, ParseError> {
+        if self.matches(&[TokenType::For]) {
+            return self.for_statement();
+        }
+
+        if self.matches(&[TokenType::If]) {
+            return self.if_statement();
+        }
+
         if self.matches(&[TokenType::Print]) {
             return self.print_statement();
         }
 
+        if self.matches(&[TokenType::Return]) {
+            return self.return_statement();
+        }
+
+        if self.matches(&[TokenType::While]) {
+            return self.while_statement();
+        }
+
+        if self.matches(&[TokenType::LeftBrace]) {
+            let start = self.previous_span();
+            let statements = self.block()?;
+            return Ok(Stmt::Block {
+                statements,
+                span: self.span_from(start),
+            });
+        }
+
         self.expr_statement()
     }
 
@@ -102,21 +219,151 @@ impl Parser {
         let expr = self.expression()?;
         self.consume_if(TokenType::Semicolon)?;
         Ok(Stmt::Expression {
+            span: self.span_from(expr.span()),
             expression: Box::new(expr),
         })
     }
 
+    fn for_statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous_span();
+        self.consume_if(TokenType::LeftParen)?;
+
+        let initializer = if self.matches(&[TokenType::Semicolon]) {
+            None
+        } else if self.matches(&[TokenType::Var]) {
+            Some(self.var_declaration()?)
+        } else {
+            Some(self.expr_statement()?)
+        };
+
+        let condition = if self.peek().token_type != TokenType::Semicolon {
+            self.expression()?
+        } else {
+            Expr::Literal {
+                value: Literal::Boolean(true),
+                span: start,
+            }
+        };
+        self.consume_if(TokenType::Semicolon)?;
+
+        let increment = if self.peek().token_type != TokenType::RightParen {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume_if(TokenType::RightParen)?;
+
+        let mut body = self.statement()?;
+        let span = self.span_from(start);
+
+        // for (init; cond; incr) body  =>  { init; while (cond) { body; incr; } }
+        if let Some(increment) = increment {
+            body = Stmt::Block {
+                span: body.span().to(increment.span()),
+                statements: vec![
+                    body,
+                    Stmt::Expression {
+                        span: increment.span(),
+                        expression: Box::new(increment),
+                    },
+                ],
+            };
+        }
+
+        body = Stmt::While {
+            condition: Box::new(condition),
+            body: Box::new(body),
+            span,
+        };
+
+        if let Some(initializer) = initializer {
+            body = Stmt::Block {
+                statements: vec![initializer, body],
+                span,
+            };
+        }
+
+        Ok(body)
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous_span();
+        self.consume_if(TokenType::LeftParen)?;
+        let condition = self.expression()?;
+        self.consume_if(TokenType::RightParen)?;
+
+        let then_branch = self.statement()?;
+        let else_branch = if self.matches(&[TokenType::Else]) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+
+        Ok(Stmt::If {
+            condition: Box::new(condition),
+            then_branch: Box::new(then_branch),
+            else_branch,
+            span: self.span_from(start),
+        })
+    }
+
+    fn return_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous();
+
+        let value = if self.peek().token_type != TokenType::Semicolon {
+            Some(Box::new(self.expression()?))
+        } else {
+            None
+        };
+        self.consume_if(TokenType::Semicolon)?;
+
+        Ok(Stmt::Return {
+            span: self.span_from(keyword.span),
+            keyword,
+            value,
+        })
+    }
+
+    fn while_statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous_span();
+        self.consume_if(TokenType::LeftParen)?;
+        let condition = self.expression()?;
+        self.consume_if(TokenType::RightParen)?;
+
+        let body = self.statement()?;
+
+        Ok(Stmt::While {
+            condition: Box::new(condition),
+            body: Box::new(body),
+            span: self.span_from(start),
+        })
+    }
+
     fn print_statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.previous_span();
         let expr = self.expression()?;
         self.consume_if(TokenType::Semicolon)?;
         Ok(Stmt::Print {
             expression: Box::new(expr),
+            span: self.span_from(start),
         })
     }
 
+    fn block(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        let mut statements: Vec<Stmt> = vec![];
+
+        while self.peek().token_type != TokenType::RightBrace && !self.is_at_end() {
+            statements.push(self.declaration()?);
+        }
+
+        self.consume_if(TokenType::RightBrace)?;
+
+        Ok(statements)
+    }
+
     fn expression(&mut self) -> Result<Expr, ParseError> {
         // a == != b
-        self.block()
+        self.comma()
     }
 
     fn is_at_end(&self) -> bool {
@@ -178,13 +425,28 @@ impl Parser {
         self.tokens[self.current - 1].clone()
     }
 
-    fn block(&mut self) -> Result<Expr, ParseError> {
-        let mut expr = self.ternary()?;
+    fn previous_span(&self) -> Span {
+        self.tokens[self.current - 1].span
+    }
+
+    /// The span from `start` to the end of the most recently consumed token.
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.previous_span())
+    }
+
+    fn comma(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.assignment()?;
+
+        // Reference Lox has no comma operator.
+        if self.dialect == Dialect::Strict {
+            return Ok(expr);
+        }
 
         while self.matches(&[TokenType::Comma]) {
             let previous = self.previous();
-            let right = self.ternary()?;
+            let right = self.assignment()?;
             expr = Expr::Binary {
+                span: expr.span().to(right.span()),
                 left: Box::new(expr),
                 operator: previous,
                 right: Box::new(right),
@@ -194,8 +456,46 @@ impl Parser {
         Ok(expr)
     }
 
+    fn assignment(&mut self) -> Result<Expr, ParseError> {
+        let expr = self.ternary()?;
+
+        if self.matches(&[TokenType::Equal]) {
+            let equals = self.previous();
+            let value = self.assignment()?;
+
+            match expr {
+                Expr::Variable { name, span, .. } => {
+                    return Ok(Expr::Assign {
+                        name,
+                        span: span.to(value.span()),
+                        value: Box::new(value),
+                        id: ExprId::next(),
+                    });
+                }
+                Expr::Get { object, name, span } => {
+                    return Ok(Expr::Set {
+                        object,
+                        name,
+                        span: span.to(value.span()),
+                        value: Box::new(value),
+                    });
+                }
+                _ => {}
+            }
+
+            self.report_error("P0005", "Invalid assignment target.", equals);
+        }
+
+        Ok(expr)
+    }
+
     fn ternary(&mut self) -> Result<Expr, ParseError> {
-        let mut expr = self.equality()?;
+        let mut expr = self.or()?;
+
+        // Reference Lox has no conditional operator either.
+        if self.dialect == Dialect::Strict {
+            return Ok(expr);
+        }
 
         if self.matches(&[TokenType::Question]) {
             let positive = self.ternary()?;
@@ -203,6 +503,7 @@ impl Parser {
             let negative = self.ternary()?;
 
             expr = Expr::Ternary {
+                span: expr.span().to(negative.span()),
                 condition: Box::new(expr),
                 positive: Box::new(positive),
                 negative: Box::new(negative),
@@ -214,6 +515,40 @@ impl Parser {
         Ok(expr)
     }
 
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.and()?;
+
+        while self.matches(&[TokenType::Or]) {
+            let previous = self.previous();
+            let right = self.and()?;
+            expr = Expr::Logical {
+                span: expr.span().to(right.span()),
+                left: Box::new(expr),
+                operator: previous,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.equality()?;
+
+        while self.matches(&[TokenType::And]) {
+            let previous = self.previous();
+            let right = self.equality()?;
+            expr = Expr::Logical {
+                span: expr.span().to(right.span()),
+                left: Box::new(expr),
+                operator: previous,
+                right: Box::new(right),
+            };
+        }
+
+        Ok(expr)
+    }
+
     fn equality(&mut self) -> Result<Expr, ParseError> {
         // a > >= < <= b
         let mut expr = self.comparison()?;
@@ -222,6 +557,7 @@ impl Parser {
             let previous = self.previous();
             let right = self.comparison()?;
             expr = Expr::Binary {
+                span: expr.span().to(right.span()),
                 left: Box::new(expr),
                 operator: previous,
                 right: Box::new(right),
@@ -244,6 +580,7 @@ impl Parser {
             let previous = self.previous();
             let right = self.term()?;
             expr = Expr::Binary {
+                span: expr.span().to(right.span()),
                 left: Box::new(expr),
                 operator: previous,
                 right: Box::new(right),
@@ -261,6 +598,7 @@ impl Parser {
             let previous = self.previous();
             let right = self.factor()?;
             expr = Expr::Binary {
+                span: expr.span().to(right.span()),
                 left: Box::new(expr),
                 operator: previous,
                 right: Box::new(right),
@@ -277,6 +615,7 @@ impl Parser {
             let previous = self.previous();
             let right = self.unary()?;
             expr = Expr::Binary {
+                span: expr.span().to(right.span()),
                 left: Box::new(expr),
                 operator: previous,
                 right: Box::new(right),
@@ -289,23 +628,101 @@ impl Parser {
     fn unary(&mut self) -> Result<Expr, ParseError> {
         if self.matches(&[TokenType::Bang, TokenType::Minus]) {
             let previous = self.previous();
-            let value = self.primary()?;
+            let value = self.unary()?;
 
             return Ok(Expr::Unary {
+                span: previous.span.to(value.span()),
                 operator: previous,
-                right: Box::new(Expr::from(value)),
+                right: Box::new(value),
             });
         }
 
-        let value = self.primary()?;
+        self.call()
+    }
+
+    fn call(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.matches(&[TokenType::LeftParen]) {
+                expr = self.finish_call(expr)?;
+            } else if self.matches(&[TokenType::Dot]) {
+                let name = self.consume_if(TokenType::Identifier)?;
+                expr = Expr::Get {
+                    span: expr.span().to(name.span),
+                    object: Box::new(expr),
+                    name,
+                };
+            } else {
+                break;
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn finish_call(&mut self, callee: Expr) -> Result<Expr, ParseError> {
+        let mut arguments = vec![];
+
+        if self.peek().token_type != TokenType::RightParen {
+            loop {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    let token = self.peek().clone();
+                    self.report_error(
+                        "P0007",
+                        format!("Can't have more than {} arguments.", MAX_ARGUMENTS),
+                        token,
+                    );
+                }
+
+                // Arguments are parsed below the comma operator so that
+                // `f(a, b)` passes two arguments instead of one.
+                arguments.push(self.assignment()?);
+
+                if !self.matches(&[TokenType::Comma]) {
+                    break;
+                }
+            }
+        }
 
-        return Ok(Expr::from(value));
+        let paren = self.consume_if(TokenType::RightParen)?;
+
+        Ok(Expr::Call {
+            span: callee.span().to(paren.span),
+            callee: Box::new(callee),
+            paren,
+            arguments,
+        })
     }
 
     fn primary(&mut self) -> Result<Expr, ParseError> {
+        if self.matches(&[TokenType::This]) {
+            return Ok(Expr::This {
+                keyword: self.previous(),
+                id: ExprId::next(),
+                span: self.previous_span(),
+            });
+        }
+
+        if self.matches(&[TokenType::Super]) {
+            let keyword = self.previous();
+            self.consume_if(TokenType::Dot)?;
+            let method = self.consume_if(TokenType::Identifier)?;
+            return Ok(Expr::Super {
+                span: keyword.span.to(method.span),
+                keyword,
+                method,
+                id: ExprId::next(),
+            });
+        }
+
         if self.matches(&[TokenType::Identifier]) {
             let previous = self.previous();
-            return Ok(Expr::Variable { name: previous });
+            return Ok(Expr::Variable {
+                span: previous.span,
+                name: previous,
+                id: ExprId::next(),
+            });
         }
 
         if self.matches(&[
@@ -318,14 +735,17 @@ impl Parser {
             let previous = self.previous();
             return Ok(Expr::Literal {
                 value: previous.literal.unwrap(),
+                span: previous.span,
             });
         }
 
         if self.matches(&[TokenType::LeftParen]) {
+            let start = self.previous_span();
             let expr = self.expression()?;
             self.consume_if(TokenType::RightParen)?;
             return Ok(Expr::Grouping {
                 expression: Box::new(expr),
+                span: self.span_from(start),
             });
         }
 
@@ -344,6 +764,7 @@ impl Parser {
                 | TokenType::BangEqual
         ) {
             self.report_error(
+                "P0004",
                 format!("Expected operand before {}", current_token.lexeme),
                 current_token.clone(),
             );
@@ -354,8 +775,10 @@ impl Parser {
         }
 
         Err(ParseError {
+            code: "P0002",
             message: "Invalid expression (primary)".to_string(),
             line: current_token.line,
+            span: current_token.span,
         })
     }
 
@@ -364,8 +787,10 @@ impl Parser {
 
         if self.is_at_end() {
             return Err(ParseError {
+                code: "P0003",
                 message: "Beyond tokens' length".to_string(),
                 line: current_token.line,
+                span: current_token.span,
             });
         }
 
@@ -379,15 +804,19 @@ impl Parser {
 
         if self.is_at_end() {
             return Err(ParseError {
+                code: "P0003",
                 message: "Beyond tokens' length".to_string(),
                 line: current_token.line,
+                span: current_token.span,
             });
         }
 
         if current_token.token_type != token_type {
             return Err(ParseError {
+                code: "P0001",
                 message: format!("Expected {} after expression.", token_type),
                 line: current_token.line,
+                span: current_token.span,
             });
         }
 
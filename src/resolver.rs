@@ -0,0 +1,362 @@
+use std::{collections::HashMap, error::Error, fmt::Display};
+
+use crate::{
+    expr::{Expr, ExprId},
+    interpreter::Locals,
+    stmt::{FunctionDecl, Stmt},
+    token::{Span, Token},
+};
+
+#[derive(Clone, Debug)]
+pub struct ResolveError {
+    pub code: &'static str,
+    pub line: usize,
+    pub message: String,
+    pub span: Span,
+}
+
+impl Display for ResolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[line {}:{}] {}",
+            self.line, self.span.column, self.message
+        )
+    }
+}
+
+impl Error for ResolveError {}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Initializer,
+    Method,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+/// Static pass run between parsing and interpretation. It works out how many
+/// scopes separate each local variable reference from its binding, for the
+/// interpreter, and reports scoping mistakes before any code runs.
+pub struct Resolver {
+    locals: Locals,
+    /// Each scope maps a name to whether its initializer has finished.
+    scopes: Vec<HashMap<String, bool>>,
+    current_function: FunctionType,
+    current_class: ClassType,
+    errors: Vec<ResolveError>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Resolver {
+            locals: HashMap::new(),
+            scopes: vec![],
+            current_function: FunctionType::None,
+            current_class: ClassType::None,
+            errors: vec![],
+        }
+    }
+
+    /// Resolves a whole program and returns the scope depths of its local
+    /// variable references.
+    pub fn resolve(&mut self, statements: &[Stmt]) -> Result<Locals, Vec<ResolveError>> {
+        self.resolve_statements(statements);
+
+        if !self.errors.is_empty() {
+            return Err(std::mem::take(&mut self.errors));
+        }
+
+        Ok(std::mem::take(&mut self.locals))
+    }
+
+    fn report_error<T: Into<String>>(&mut self, code: &'static str, message: T, token: &Token) {
+        self.errors.push(ResolveError {
+            code,
+            message: message.into(),
+            line: token.line,
+            span: token.span,
+        });
+    }
+
+    fn resolve_statements(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            self.resolve_statement(statement);
+        }
+    }
+
+    fn resolve_statement(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block { statements, .. } => {
+                self.begin_scope();
+                self.resolve_statements(statements);
+                self.end_scope();
+            }
+            Stmt::Var {
+                name, initializer, ..
+            } => {
+                self.declare(name);
+                self.resolve_expression(initializer);
+                self.define(name);
+            }
+            Stmt::Function { declaration, .. } => {
+                self.declare(&declaration.name);
+                self.define(&declaration.name);
+                self.resolve_function(declaration, FunctionType::Function);
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+                ..
+            } => {
+                let enclosing_class = self.current_class;
+                self.current_class = ClassType::Class;
+
+                self.declare(name);
+                self.define(name);
+
+                if let Some(superclass) = superclass {
+                    if let Expr::Variable {
+                        name: superclass_name,
+                        ..
+                    } = superclass.as_ref()
+                        && superclass_name.lexeme == name.lexeme
+                    {
+                        self.report_error(
+                            "A0008",
+                            "A class can't inherit from itself.",
+                            superclass_name,
+                        );
+                    }
+
+                    self.current_class = ClassType::Subclass;
+                    self.resolve_expression(superclass);
+
+                    self.begin_scope();
+                    self.define_name("super");
+                }
+
+                self.begin_scope();
+                self.define_name("this");
+
+                for method in methods {
+                    let function_type = if method.name.lexeme == "init" {
+                        FunctionType::Initializer
+                    } else {
+                        FunctionType::Method
+                    };
+                    self.resolve_function(method, function_type);
+                }
+
+                self.end_scope();
+
+                if superclass.is_some() {
+                    self.end_scope();
+                }
+
+                self.current_class = enclosing_class;
+            }
+            Stmt::Expression { expression, .. } | Stmt::Print { expression, .. } => {
+                self.resolve_expression(expression);
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.resolve_expression(condition);
+                self.resolve_statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_statement(else_branch);
+                }
+            }
+            Stmt::While {
+                condition, body, ..
+            } => {
+                self.resolve_expression(condition);
+                self.resolve_statement(body);
+            }
+            Stmt::Return { keyword, value, .. } => {
+                if self.current_function == FunctionType::None {
+                    self.report_error("A0003", "Can't return from top-level code.", keyword);
+                }
+
+                if let Some(value) = value {
+                    if self.current_function == FunctionType::Initializer {
+                        self.report_error(
+                            "A0004",
+                            "Can't return a value from an initializer.",
+                            keyword,
+                        );
+                    }
+                    self.resolve_expression(value);
+                }
+            }
+        }
+    }
+
+    fn resolve_expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Variable { name, id, .. } => {
+                if self
+                    .scopes
+                    .last()
+                    .and_then(|scope| scope.get(&name.lexeme))
+                    .is_some_and(|defined| !defined)
+                {
+                    self.report_error(
+                        "A0001",
+                        "Can't read local variable in its own initializer.",
+                        name,
+                    );
+                }
+
+                self.resolve_local(*id, name);
+            }
+            Expr::Assign {
+                name, value, id, ..
+            } => {
+                self.resolve_expression(value);
+                self.resolve_local(*id, name);
+            }
+            Expr::This { keyword, id, .. } => {
+                if self.current_class == ClassType::None {
+                    self.report_error("A0005", "Can't use \"this\" outside of a class.", keyword);
+                    return;
+                }
+
+                self.resolve_local(*id, keyword);
+            }
+            Expr::Super { keyword, id, .. } => {
+                match self.current_class {
+                    ClassType::None => {
+                        self.report_error(
+                            "A0006",
+                            "Can't use \"super\" outside of a class.",
+                            keyword,
+                        );
+                    }
+                    ClassType::Class => {
+                        self.report_error(
+                            "A0007",
+                            "Can't use \"super\" in a class with no superclass.",
+                            keyword,
+                        );
+                    }
+                    ClassType::Subclass => {}
+                }
+
+                self.resolve_local(*id, keyword);
+            }
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.resolve_expression(left);
+                self.resolve_expression(right);
+            }
+            Expr::Unary { right, .. } => self.resolve_expression(right),
+            Expr::Grouping { expression, .. } => self.resolve_expression(expression),
+            Expr::Ternary {
+                condition,
+                positive,
+                negative,
+                ..
+            } => {
+                self.resolve_expression(condition);
+                self.resolve_expression(positive);
+                self.resolve_expression(negative);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.resolve_expression(callee);
+                for argument in arguments {
+                    self.resolve_expression(argument);
+                }
+            }
+            Expr::Get { object, .. } => self.resolve_expression(object),
+            Expr::Set { object, value, .. } => {
+                self.resolve_expression(value);
+                self.resolve_expression(object);
+            }
+            Expr::Literal { .. } => {}
+        }
+    }
+
+    fn resolve_function(&mut self, declaration: &FunctionDecl, function_type: FunctionType) {
+        let enclosing_function = self.current_function;
+        self.current_function = function_type;
+
+        self.begin_scope();
+        for param in &declaration.params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.resolve_statements(&declaration.body);
+        self.end_scope();
+
+        self.current_function = enclosing_function;
+    }
+
+    /// Records the depth of the innermost scope binding `name`. Names not found
+    /// in any scope are left unresolved and looked up as globals at runtime.
+    fn resolve_local(&mut self, id: ExprId, name: &Token) {
+        let depth = self
+            .scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(&name.lexeme));
+
+        if let Some(depth) = depth {
+            self.locals.insert(id, depth);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+
+        if scope.contains_key(&name.lexeme) {
+            self.report_error(
+                "A0002",
+                "Already a variable with this name in this scope.",
+                name,
+            );
+            return;
+        }
+
+        scope.insert(name.lexeme.clone(), false);
+    }
+
+    fn define(&mut self, name: &Token) {
+        self.define_name(&name.lexeme);
+    }
+
+    fn define_name(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), true);
+        }
+    }
+}
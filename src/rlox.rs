@@ -1,15 +1,67 @@
-use std::io::{Write, stdin, stdout};
+use std::{
+    cell::RefCell,
+    io::{self, IsTerminal, Write, stderr, stdin, stdout},
+    rc::Rc,
+};
 use thiserror::Error;
 
 use crate::{
-    ast_printer::AstPrinter,
-    interpreter::{Interpreter, RuntimeError},
+    chunk::FunctionProto,
+    compiler::{CompileError, Compiler},
+    diagnostics::{Diagnostic, Renderer},
+    function::LoxFunction,
+    heap::{GcConfig, GcStats},
+    interpreter::{Interpreter, Locals, Output, RuntimeError, RuntimeErrorKind},
+    limits::Limits,
+    native::{IntoNative, NativeFn},
     parser::{ParseError, Parser},
+    resolver::{ResolveError, Resolver},
+    rloxc::Module,
     scanner::{ScanError, Scanner},
+    stdlib::{self, Capabilities},
+    stmt::Stmt,
+    token::{Token, TokenType},
+    value::Value,
+    vm::Vm,
 };
 
+/// Which engine executes programs once they have been parsed and resolved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    TreeWalker,
+    Vm,
+}
+
+/// Which flavour of Lox programs are written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dialect {
+    /// Reference Lox, as in the book: only `nil` and `false` are falsy, `+`
+    /// takes two numbers or two strings, the other arithmetic and comparison
+    /// operators take only numbers, `1 / 0` is infinity, and there are no
+    /// comma or conditional operators.
+    Strict,
+    /// Reference Lox plus this implementation's extensions: `0` is falsy,
+    /// `+` converts a non-string operand when the other is a string, strings
+    /// compare lexicographically, dividing by zero is an error, `a, b`
+    /// evaluates both and yields `b`, and `c ? a : b` picks one.
+    Extended,
+}
+
 pub struct Rlox {
     interpreter: Interpreter,
+    vm: Vm,
+    backend: Backend,
+    /// The backend the last script or call ran on, which differs from
+    /// `backend` after [`Rlox::run_compiled`].
+    last_run: Backend,
+    dialect: Dialect,
+    diagnostics: Box<dyn Write>,
+    /// Whether diagnostics are rendered with ANSI colors.
+    color: bool,
+    /// What the built-in natives may do; shared with them.
+    capabilities: Rc<RefCell<Capabilities>>,
+    /// The file scripts are read from, recorded in runtime errors' backtraces.
+    source_name: Option<Rc<str>>,
 }
 
 #[derive(Error, Debug)]
@@ -20,35 +72,352 @@ pub enum RloxError {
     #[error("parse error: {0}")]
     Parse(#[from] ParseError),
 
+    #[error("resolve error: {0}")]
+    Resolve(#[from] ResolveError),
+
+    #[error("compile error: {0}")]
+    Compile(#[from] CompileError),
+
     #[error("runtime error: {0}")]
     Runtime(#[from] RuntimeError),
 }
 
+impl RloxError {
+    /// The error as a one-line JSON diagnostic, for editors and other tools.
+    pub fn to_json(&self, file_name: &str) -> String {
+        Diagnostic::from(self).to_json(file_name)
+    }
+}
+
 impl Rlox {
-    pub fn new() -> Self {
+    /// Creates an instance whose programs `print` to `output` and whose
+    /// errors are reported to `diagnostics`. Scripts can't touch files, the
+    /// environment or processes until granted [`Capabilities`].
+    pub fn new(
+        backend: Backend,
+        output: impl Write + 'static,
+        diagnostics: impl Write + 'static,
+    ) -> Self {
+        let output: Output = Rc::new(RefCell::new(output));
+        let capabilities = Rc::new(RefCell::new(Capabilities::default()));
+
+        let mut rlox = Self {
+            interpreter: Interpreter::new(Rc::clone(&output)),
+            vm: Vm::new(output),
+            backend,
+            last_run: backend,
+            dialect: Dialect::Extended,
+            diagnostics: Box::new(diagnostics),
+            color: false,
+            capabilities: Rc::clone(&capabilities),
+            source_name: None,
+        };
+        stdlib::install(&mut rlox, capabilities);
+        rlox
+    }
+
+    /// Creates an instance printing to stdout and reporting to stderr.
+    pub fn with_backend(backend: Backend) -> Self {
         Self {
-            interpreter: Interpreter::new(),
+            color: stderr().is_terminal(),
+            ..Self::new(backend, stdout(), stderr())
+        }
+    }
+
+    /// The sink errors are reported to.
+    pub fn diagnostics(&mut self) -> &mut dyn Write {
+        &mut self.diagnostics
+    }
+
+    /// Renders `errors` in `source` to the diagnostics sink.
+    pub fn report_errors(
+        &mut self,
+        errors: &[RloxError],
+        source: &str,
+        file_name: &str,
+    ) -> io::Result<()> {
+        let renderer = Renderer::new(source, file_name, self.color);
+        for error in errors {
+            write!(
+                self.diagnostics,
+                "{}",
+                renderer.render(&Diagnostic::from(error))
+            )?;
         }
+
+        Ok(())
+    }
+
+    pub fn set_dialect(&mut self, dialect: Dialect) {
+        self.dialect = dialect;
+        self.interpreter.set_dialect(dialect);
+        self.vm.set_dialect(dialect);
+    }
+
+    /// Applies `config` to the garbage collectors of both backends.
+    pub fn set_gc_config(&mut self, config: GcConfig) {
+        self.interpreter.heap_mut().set_config(config);
+        self.vm.heap_mut().set_config(config);
+    }
+
+    /// Grants scripts `capabilities`, replacing those granted before.
+    ///
+    /// ```
+    /// use std::env;
+    ///
+    /// use rlox::{Backend, Capabilities, Rlox};
+    ///
+    /// let mut rlox = Rlox::with_backend(Backend::Vm);
+    /// assert!(rlox.run("read_file(\"Cargo.toml\");").is_err());
+    ///
+    /// rlox.set_capabilities(Capabilities {
+    ///     fs_read: Some(env::current_dir().unwrap()),
+    ///     ..Capabilities::default()
+    /// });
+    /// assert!(rlox.run("read_file(\"Cargo.toml\");").is_ok());
+    /// ```
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        *self.capabilities.borrow_mut() = capabilities;
+    }
+
+    /// The capabilities currently granted to scripts.
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities.borrow().clone()
+    }
+
+    /// Bounds the resources scripts may use on both backends. Each run of a
+    /// script, or call from the host, gets the full allowance.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.interpreter.set_limits(limits);
+        self.vm.set_limits(limits);
+    }
+
+    /// Collector statistics of the heap the last script or call ran on: the
+    /// VM's after [`Rlox::run_compiled`], the selected backend's otherwise.
+    pub fn gc_stats(&self) -> GcStats {
+        match self.last_run {
+            Backend::TreeWalker => self.interpreter.heap().stats(),
+            Backend::Vm => self.vm.heap().stats(),
+        }
+    }
+
+    /// Names the file the scripts passed to [`Rlox::run`] and [`Rlox::eval`]
+    /// come from, so backtraces can point at it.
+    pub fn set_source_name(&mut self, name: &str) {
+        self.source_name = Some(name.into());
     }
 
     pub fn run(&mut self, source: &str) -> Result<(), Vec<RloxError>> {
-        let mut scanner = Scanner::new(source.to_string());
+        self.eval(source).map(|_| ())
+    }
+
+    /// Runs `source` and returns the value of its last statement if that is
+    /// an expression statement, or `nil` otherwise.
+    pub fn eval(&mut self, source: &str) -> Result<Value, Vec<RloxError>> {
+        let (mut statements, locals) = self.analyze(source)?;
+
+        // Returning the final expression's value from the script hands it
+        // back to us on either backend.
+        if let Some(Stmt::Expression { expression, span }) =
+            statements.pop_if(|statement| matches!(statement, Stmt::Expression { .. }))
+        {
+            let keyword = Token::new(TokenType::Return, "return".to_string(), None, span.line)
+                .with_span(span);
+            statements.push(Stmt::Return {
+                keyword,
+                value: Some(expression),
+                span,
+            });
+        }
 
-        let tokens = scanner
+        self.last_run = self.backend;
+        let result = match self.backend {
+            Backend::TreeWalker => self.interpreter.interpret(statements, locals),
+            Backend::Vm => {
+                let script = Self::compile_statements(&statements)?;
+                self.vm.interpret(script)
+            }
+        };
+        result.map_err(|error| vec![RloxError::Runtime(self.locate(error, None))])
+    }
+
+    /// Records on the frames of `error` the file they ran from: `file`, or
+    /// else the name set with [`Rlox::set_source_name`].
+    fn locate(&self, mut error: RuntimeError, file: Option<&str>) -> RuntimeError {
+        if let Some(file) = file.map(Rc::from).or_else(|| self.source_name.clone()) {
+            for frame in &mut error.backtrace {
+                frame.file = Some(Rc::clone(&file));
+            }
+        }
+        error
+    }
+
+    /// Runs an already compiled script, such as one loaded from a `.rloxc`
+    /// module. Compiled code always runs on the VM, whatever the backend.
+    pub fn run_compiled(&mut self, module: &Module) -> Result<(), Vec<RloxError>> {
+        self.last_run = Backend::Vm;
+        self.vm
+            .interpret(Rc::clone(&module.script))
+            .map(|_| ())
+            .map_err(|error| {
+                let error = self.locate(error, Some(&module.source_name));
+                vec![RloxError::Runtime(error)]
+            })
+    }
+
+    /// Reads a global variable of the selected backend.
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        match self.backend {
+            Backend::TreeWalker => self.interpreter.get_global(name),
+            Backend::Vm => self.vm.get_global(name),
+        }
+    }
+
+    /// Defines or overwrites a global variable of the selected backend.
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        match self.backend {
+            Backend::TreeWalker => self.interpreter.set_global(name, value),
+            Backend::Vm => self.vm.set_global(name, value),
+        }
+    }
+
+    /// Calls the global function or class `name` of the selected backend with
+    /// `arguments` and returns its result.
+    ///
+    /// ```
+    /// use rlox::{Backend, Rlox, Value};
+    ///
+    /// let mut rlox = Rlox::with_backend(Backend::TreeWalker);
+    /// rlox.run("fn twice(x) { return x * 2; }").unwrap();
+    /// let result = rlox.call("twice", &[Value::from(3.0)]).unwrap();
+    /// assert_eq!(result, Value::from(6.0));
+    /// ```
+    pub fn call(&mut self, name: &str, arguments: &[Value]) -> Result<Value, RuntimeError> {
+        let token = Token::new(TokenType::Identifier, name, None, 0);
+        let callee = self
+            .get_global(name)
+            .ok_or_else(|| RuntimeError::new(RuntimeErrorKind::UndefinedVariable, &token))?;
+
+        self.last_run = self.backend;
+        let result = match self.backend {
+            Backend::TreeWalker => self.interpreter.invoke(callee, arguments.to_vec(), &token),
+            Backend::Vm => self.vm.invoke(callee, arguments),
+        };
+        result.map_err(|error| self.locate(error, None))
+    }
+
+    /// Makes `function` callable from scripts as the global `name`. It is
+    /// only called with exactly `arity` arguments.
+    ///
+    /// ```
+    /// use rlox::{Backend, Rlox, RuntimeErrorKind, Value};
+    ///
+    /// let mut rlox = Rlox::with_backend(Backend::Vm);
+    /// rlox.register_native("first", 1, |arguments| match &arguments[0] {
+    ///     Value::String(string) => Ok(Value::from(&string[..1])),
+    ///     _ => Err(RuntimeErrorKind::native("first() takes a string.")),
+    /// });
+    ///
+    /// assert_eq!(rlox.eval("first(\"lox\");").unwrap(), Value::from("l"));
+    /// ```
+    pub fn register_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value, RuntimeErrorKind> + 'static,
+    ) {
+        self.define_native(name, arity, Rc::new(function));
+    }
+
+    /// Registers a native whose parameters and result are converted from and
+    /// to Lox values automatically. Arguments of the wrong type are reported
+    /// as runtime errors before `function` is called.
+    ///
+    /// ```
+    /// use rlox::{Backend, Rlox, Value};
+    ///
+    /// let mut rlox = Rlox::with_backend(Backend::TreeWalker);
+    /// rlox.register_fn("hypot", |x: f64, y: f64| x.hypot(y));
+    ///
+    /// assert_eq!(rlox.eval("hypot(3, 4);").unwrap(), Value::Number(5.0));
+    /// assert!(rlox.eval("hypot(\"3\", 4);").is_err());
+    /// ```
+    pub fn register_fn<Args>(&mut self, name: &str, function: impl IntoNative<Args>) {
+        self.define_native(name, function.arity(), function.into_native());
+    }
+
+    fn define_native(&mut self, name: &str, arity: usize, function: NativeFn) {
+        // Natives hold no references the collector can see, so they're left
+        // out of the heaps. Both backends get them, as compiled scripts always
+        // run on the VM.
+        let function = Rc::new(LoxFunction::native(name.to_string(), arity, function));
+        self.interpreter
+            .set_global(name, Value::Function(Rc::clone(&function)));
+        self.vm.set_global(name, Value::Function(function));
+    }
+
+    /// Scans `source` into tokens, ending with an end-of-file token.
+    pub fn tokens(&self, source: &str) -> Result<Vec<Token>, Vec<RloxError>> {
+        Scanner::new(source.to_string())
             .scan_tokens()
-            .map_err(|errors| errors.into_iter().map(RloxError::Scan).collect::<Vec<_>>())?;
+            .map_err(|errors| errors.into_iter().map(RloxError::Scan).collect())
+    }
+
+    /// Scans and parses `source` without resolving or running it.
+    pub fn parse(&self, source: &str) -> Result<Vec<Stmt>, Vec<RloxError>> {
+        let tokens = self.tokens(source)?;
 
-        let mut parser = Parser::new(tokens);
-        let expr = parser
+        Parser::new(tokens, self.dialect)
             .parse()
-            .map_err(|errors| errors.into_iter().map(RloxError::Parse).collect::<Vec<_>>())?;
+            .map_err(|errors| errors.into_iter().map(RloxError::Parse).collect())
+    }
 
-        self.interpreter
-            .interpret(expr)
-            .map_err(|error| vec![RloxError::Runtime(error)])?;
+    /// Reports every static error in `source` without running it, including
+    /// the bytecode compiler's when the VM backend is selected.
+    pub fn check(&mut self, source: &str) -> Result<(), Vec<RloxError>> {
+        let (statements, _) = self.analyze(source)?;
+
+        if self.backend == Backend::Vm {
+            Self::compile_statements(&statements)?;
+        }
 
         Ok(())
     }
+
+    /// Compiles `source` to bytecode for the VM without running it.
+    /// `source_name` is kept in the module for reporting runtime errors.
+    pub fn compile(&mut self, source: &str, source_name: &str) -> Result<Module, Vec<RloxError>> {
+        let (statements, _) = self.analyze(source)?;
+        Ok(Module {
+            source_name: source_name.to_string(),
+            script: Self::compile_statements(&statements)?,
+        })
+    }
+
+    fn compile_statements(statements: &[Stmt]) -> Result<Rc<FunctionProto>, Vec<RloxError>> {
+        Compiler::new().compile(statements).map_err(|errors| {
+            errors
+                .into_iter()
+                .map(RloxError::Compile)
+                .collect::<Vec<_>>()
+        })
+    }
+
+    /// Scans, parses and resolves `source`. The scope depths found by the
+    /// resolver are only used by the interpreter; the compiler redoes that
+    /// work itself.
+    fn analyze(&mut self, source: &str) -> Result<(Vec<Stmt>, Locals), Vec<RloxError>> {
+        let expr = self.parse(source)?;
+
+        let locals = Resolver::new().resolve(&expr).map_err(|errors| {
+            errors
+                .into_iter()
+                .map(RloxError::Resolve)
+                .collect::<Vec<_>>()
+        })?;
+
+        Ok((expr, locals))
+    }
 }
 
 pub struct Repl {
@@ -75,9 +444,7 @@ impl Repl {
             }
 
             if let Err(errors) = self.rlox.run(input) {
-                for error in errors {
-                    eprintln!("{}", error);
-                }
+                self.rlox.report_errors(&errors, input, "<repl>").unwrap();
             }
         }
     }
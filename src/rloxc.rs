@@ -0,0 +1,746 @@
+//! The `.rloxc` compiled module format.
+//!
+//! A module starts with a fixed header: the magic bytes, a little-endian
+//! `u16` format version and a `u32` checksum of everything after it. The
+//! payload holds the source file name followed by the script function, whose
+//! constant pool nests the prototypes of the functions it declares.
+
+use std::rc::Rc;
+
+use thiserror::Error;
+
+use crate::{
+    chunk::{Chunk, Constant, FunctionProto, OpCode},
+    disassembler::Disassembler,
+    token::Span,
+};
+
+pub const MAGIC: [u8; 6] = *b"RLOXC\0";
+
+/// Bumped whenever the layout or the meaning of any opcode changes.
+pub const FORMAT_VERSION: u16 = 1;
+
+const HEADER_LENGTH: usize = MAGIC.len() + 2 + 4;
+
+const TAG_NUMBER: u8 = 0;
+const TAG_STRING: u8 = 1;
+const TAG_FUNCTION: u8 = 2;
+
+/// How deeply function prototypes may nest. Decoding and validation recurse
+/// once per level, so this keeps a crafted module from exhausting the stack.
+const MAX_NESTING: usize = 256;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum LoadError {
+    #[error("not a compiled rlox module")]
+    NotAModule,
+
+    #[error("module format version {found} is not supported (expected {expected})")]
+    VersionMismatch { found: u16, expected: u16 },
+
+    #[error("module is truncated")]
+    Truncated,
+
+    #[error("module checksum does not match its contents")]
+    ChecksumMismatch,
+
+    #[error("module is malformed: {0}")]
+    Malformed(String),
+}
+
+/// A compiled script together with the name of the source it came from, so
+/// runtime errors can still point at the original file.
+pub struct Module {
+    pub source_name: String,
+    pub(crate) script: Rc<FunctionProto>,
+}
+
+impl Module {
+    /// A listing of the script's bytecode and that of every function in it.
+    pub fn disassemble(&self) -> String {
+        Disassembler.disassemble(&self.script)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut payload = Encoder::default();
+        payload.string(&self.source_name);
+        payload.function(&self.script);
+
+        let mut bytes = Vec::with_capacity(HEADER_LENGTH + payload.bytes.len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&checksum(&payload.bytes).to_le_bytes());
+        bytes.extend_from_slice(&payload.bytes);
+        bytes
+    }
+
+    /// Decodes and validates a module. The checksum catches accidental
+    /// corruption. The bytecode checks then make sure every instruction only
+    /// refers to constants, upvalues and stack slots that exist, so even a
+    /// crafted module can't make the VM read out of bounds.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Module, LoadError> {
+        if bytes.len() < MAGIC.len() || bytes[..MAGIC.len()] != MAGIC {
+            return Err(LoadError::NotAModule);
+        }
+
+        let mut header = Decoder::new(&bytes[MAGIC.len()..]);
+        let version = header.u16()?;
+        if version != FORMAT_VERSION {
+            return Err(LoadError::VersionMismatch {
+                found: version,
+                expected: FORMAT_VERSION,
+            });
+        }
+
+        let expected_checksum = header.u32()?;
+        let payload = &bytes[HEADER_LENGTH..];
+        if checksum(payload) != expected_checksum {
+            return Err(LoadError::ChecksumMismatch);
+        }
+
+        let mut decoder = Decoder::new(payload);
+        let source_name = decoder.string()?;
+        let script = decoder.function()?;
+
+        if decoder.position != payload.len() {
+            return Err(malformed("trailing bytes after the script"));
+        }
+
+        validate(&script)?;
+
+        Ok(Module {
+            source_name,
+            script: Rc::new(script),
+        })
+    }
+}
+
+/// 32-bit FNV-1a.
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+fn malformed(message: impl Into<String>) -> LoadError {
+    LoadError::Malformed(message.into())
+}
+
+#[derive(Default)]
+struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u32(&mut self, value: usize) {
+        let value = u32::try_from(value).expect("module sections fit in a u32");
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn function(&mut self, function: &FunctionProto) {
+        self.string(&function.name);
+        self.u32(function.arity);
+        self.u32(function.upvalue_count);
+        self.chunk(&function.chunk);
+    }
+
+    fn chunk(&mut self, chunk: &Chunk) {
+        self.u32(chunk.code.len());
+        self.bytes.extend_from_slice(&chunk.code);
+
+        self.u32(chunk.constants.len());
+        for constant in &chunk.constants {
+            match constant {
+                Constant::Number(number) => {
+                    self.u8(TAG_NUMBER);
+                    self.bytes.extend_from_slice(&number.to_le_bytes());
+                }
+                Constant::String(string) => {
+                    self.u8(TAG_STRING);
+                    self.string(string);
+                }
+                Constant::Function(function) => {
+                    self.u8(TAG_FUNCTION);
+                    self.function(function);
+                }
+            }
+        }
+
+        self.u32(chunk.spans.len());
+        for (offset, span) in &chunk.spans {
+            self.u32(*offset);
+            for field in [
+                span.start,
+                span.end,
+                span.line,
+                span.column,
+                span.end_line,
+                span.end_column,
+            ] {
+                self.u32(field);
+            }
+        }
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    /// Functions being decoded, counting the one in progress.
+    depth: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Decoder {
+            bytes,
+            position: 0,
+            depth: 0,
+        }
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], LoadError> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(LoadError::Truncated)?;
+
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, LoadError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, LoadError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn length(&mut self) -> Result<usize, LoadError> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn string(&mut self) -> Result<String, LoadError> {
+        let length = self.length()?;
+        let bytes = self.take(length)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| malformed("string is not valid UTF-8"))
+    }
+
+    fn function(&mut self) -> Result<FunctionProto, LoadError> {
+        if self.depth == MAX_NESTING {
+            return Err(malformed(format!(
+                "functions nested more than {} deep",
+                MAX_NESTING
+            )));
+        }
+
+        self.depth += 1;
+        let function = FunctionProto {
+            name: self.string()?,
+            arity: self.length()?,
+            upvalue_count: self.length()?,
+            chunk: self.chunk()?,
+        };
+        self.depth -= 1;
+        Ok(function)
+    }
+
+    fn chunk(&mut self) -> Result<Chunk, LoadError> {
+        let code_length = self.length()?;
+        let code = self.take(code_length)?.to_vec();
+
+        let constant_count = self.length()?;
+        let mut constants = Vec::new();
+        for _ in 0..constant_count {
+            constants.push(match self.u8()? {
+                TAG_NUMBER => {
+                    let bytes = self.take(8)?;
+                    let mut number = [0; 8];
+                    number.copy_from_slice(bytes);
+                    Constant::Number(f64::from_le_bytes(number))
+                }
+                TAG_STRING => Constant::String(self.string()?.into()),
+                TAG_FUNCTION => Constant::Function(Rc::new(self.function()?)),
+                tag => return Err(malformed(format!("unknown constant tag {}", tag))),
+            });
+        }
+
+        let span_count = self.length()?;
+        let mut spans = Vec::new();
+        for _ in 0..span_count {
+            let offset = self.length()?;
+            let span = Span {
+                start: self.length()?,
+                end: self.length()?,
+                line: self.length()?,
+                column: self.length()?,
+                end_line: self.length()?,
+                end_column: self.length()?,
+            };
+            spans.push((offset, span));
+        }
+
+        Ok(Chunk {
+            code,
+            constants,
+            spans,
+        })
+    }
+}
+
+/// Checks that every instruction of `function` and its nested functions
+/// decodes, refers to constants of the right kind, jumps to the start of an
+/// instruction and keeps the stack balanced. The decoder has already bounded
+/// how deeply this recurses.
+fn validate(function: &FunctionProto) -> Result<(), LoadError> {
+    let chunk = &function.chunk;
+    let code = &chunk.code;
+    let context = |message: String| malformed(format!("in {}: {}", function.name, message));
+
+    let u16_at = |offset: usize| -> Result<usize, LoadError> {
+        match code.get(offset..offset + 2) {
+            Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]]) as usize),
+            None => Err(context(format!("operand at {} runs past the end", offset))),
+        }
+    };
+    let constant_at = |offset: usize| -> Result<&Constant, LoadError> {
+        let index = u16_at(offset)?;
+        chunk
+            .constants
+            .get(index)
+            .ok_or_else(|| context(format!("constant {} does not exist", index)))
+    };
+    let string_at = |offset: usize| -> Result<(), LoadError> {
+        match constant_at(offset)? {
+            Constant::String(_) => Ok(()),
+            _ => Err(context(format!(
+                "instruction at {} expects a name",
+                offset - 1
+            ))),
+        }
+    };
+
+    let mut boundaries = vec![];
+    let mut jumps = vec![];
+    let mut last = None;
+    let mut offset = 0;
+
+    while offset < code.len() {
+        boundaries.push(offset);
+
+        let op = OpCode::try_from(code[offset])
+            .map_err(|byte| context(format!("unknown opcode {} at {}", byte, offset)))?;
+        last = Some(op);
+
+        offset += match op {
+            OpCode::Constant => match constant_at(offset + 1)? {
+                Constant::Function(_) => {
+                    return Err(context(format!("constant at {} is a function", offset)));
+                }
+                _ => 3,
+            },
+            OpCode::GetGlobal
+            | OpCode::DefineGlobal
+            | OpCode::SetGlobal
+            | OpCode::GetProperty
+            | OpCode::SetProperty
+            | OpCode::GetSuper => {
+                string_at(offset + 1)?;
+                3
+            }
+            OpCode::GetLocal | OpCode::SetLocal => {
+                u16_at(offset + 1)?;
+                3
+            }
+            OpCode::GetUpvalue | OpCode::SetUpvalue => {
+                if u16_at(offset + 1)? >= function.upvalue_count {
+                    return Err(context(format!("upvalue out of range at {}", offset)));
+                }
+                3
+            }
+            OpCode::Jump | OpCode::JumpIfFalse => {
+                jumps.push(offset + 3 + u16_at(offset + 1)?);
+                3
+            }
+            OpCode::Loop => {
+                let distance = u16_at(offset + 1)?;
+                let target = (offset + 3)
+                    .checked_sub(distance)
+                    .ok_or_else(|| context(format!("loop at {} jumps before the start", offset)))?;
+                jumps.push(target);
+                3
+            }
+            OpCode::Call => {
+                if code.get(offset + 1).is_none() {
+                    return Err(context(format!(
+                        "operand at {} runs past the end",
+                        offset + 1
+                    )));
+                }
+                2
+            }
+            OpCode::Closure => {
+                let Constant::Function(nested) = constant_at(offset + 1)? else {
+                    return Err(context(format!("closure at {} needs a function", offset)));
+                };
+                validate(nested)?;
+
+                for capture in 0..nested.upvalue_count {
+                    let operand = offset + 3 + capture * 3;
+                    let is_local = code.get(operand).copied();
+                    let index = u16_at(operand + 1)?;
+
+                    match is_local {
+                        Some(1) => {}
+                        Some(0) if index < function.upvalue_count => {}
+                        _ => return Err(context(format!("bad capture at {}", operand))),
+                    }
+                }
+                3 + nested.upvalue_count * 3
+            }
+            OpCode::Class => {
+                string_at(offset + 1)?;
+                u16_at(offset + 3)?;
+                match code.get(offset + 5) {
+                    Some(0 | 1) => 6,
+                    _ => return Err(context(format!("bad superclass flag at {}", offset + 5))),
+                }
+            }
+            OpCode::Nil
+            | OpCode::True
+            | OpCode::False
+            | OpCode::Pop
+            | OpCode::Equal
+            | OpCode::NotEqual
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::Comma
+            | OpCode::Not
+            | OpCode::Negate
+            | OpCode::Print
+            | OpCode::CloseUpvalue
+            | OpCode::Return => 1,
+        };
+    }
+
+    if last != Some(OpCode::Return) {
+        return Err(context("code does not end in a return".to_string()));
+    }
+
+    if let Some(target) = jumps
+        .iter()
+        .find(|target| boundaries.binary_search(target).is_err())
+    {
+        return Err(context(format!("jump to {} is not an instruction", target)));
+    }
+
+    check_stack(function)
+}
+
+/// Follows every path through `function`, already known to decode, checking
+/// that each instruction finds its operands on the stack and only touches
+/// local slots that exist, and that paths meeting at an instruction agree on
+/// the depth of the stack.
+fn check_stack(function: &FunctionProto) -> Result<(), LoadError> {
+    let chunk = &function.chunk;
+    let code = &chunk.code;
+    let context = |message: String| malformed(format!("in {}: {}", function.name, message));
+    let u16_at = |offset: usize| u16::from_be_bytes([code[offset], code[offset + 1]]) as usize;
+
+    // Depths are counted from the frame's base, whose slot 0 holds the
+    // function itself, or `this` in methods.
+    let mut depths = vec![None; code.len()];
+    let mut pending = vec![(0, function.arity + 1)];
+
+    while let Some((offset, depth)) = pending.pop() {
+        match depths[offset] {
+            Some(seen) if seen == depth => continue,
+            Some(_) => {
+                return Err(context(format!(
+                    "paths to {} leave different stack depths",
+                    offset
+                )));
+            }
+            None => depths[offset] = Some(depth),
+        }
+
+        let local = |slot: usize| {
+            if slot < depth {
+                Ok(())
+            } else {
+                Err(context(format!(
+                    "local slot {} out of range at {}",
+                    slot, offset
+                )))
+            }
+        };
+
+        let op = OpCode::try_from(code[offset]).expect("opcodes were checked");
+
+        // How many values the instruction needs on the stack, how many it
+        // leaves in their place, and its length.
+        let (needs, leaves, length) = match op {
+            OpCode::Constant | OpCode::GetGlobal | OpCode::GetUpvalue => (0, 1, 3),
+            OpCode::Nil | OpCode::True | OpCode::False => (0, 1, 1),
+            OpCode::GetLocal => {
+                local(u16_at(offset + 1))?;
+                (0, 1, 3)
+            }
+            OpCode::SetLocal => {
+                local(u16_at(offset + 1))?;
+                (1, 1, 3)
+            }
+            OpCode::DefineGlobal => (1, 0, 3),
+            OpCode::SetGlobal | OpCode::SetUpvalue | OpCode::GetProperty => (1, 1, 3),
+            OpCode::SetProperty | OpCode::GetSuper => (2, 1, 3),
+            OpCode::Equal
+            | OpCode::NotEqual
+            | OpCode::Greater
+            | OpCode::GreaterEqual
+            | OpCode::Less
+            | OpCode::LessEqual
+            | OpCode::Add
+            | OpCode::Subtract
+            | OpCode::Multiply
+            | OpCode::Divide
+            | OpCode::Comma => (2, 1, 1),
+            OpCode::Not | OpCode::Negate => (1, 1, 1),
+            OpCode::Pop | OpCode::Print | OpCode::CloseUpvalue | OpCode::Return => (1, 0, 1),
+            OpCode::Jump | OpCode::Loop => (0, 0, 3),
+            OpCode::JumpIfFalse => (1, 1, 3),
+            OpCode::Call => (code[offset + 1] as usize + 1, 1, 2),
+            OpCode::Closure => {
+                let Constant::Function(nested) = &chunk.constants[u16_at(offset + 1)] else {
+                    unreachable!("closures were checked to refer to functions");
+                };
+                for capture in 0..nested.upvalue_count {
+                    let operand = offset + 3 + capture * 3;
+                    if code[operand] == 1 {
+                        local(u16_at(operand + 1))?;
+                    }
+                }
+                (0, 1, 3 + nested.upvalue_count * 3)
+            }
+            OpCode::Class => {
+                // The superclass stays below the new class.
+                let superclass = code[offset + 5] as usize;
+                (u16_at(offset + 3) + superclass, superclass + 1, 6)
+            }
+        };
+
+        if depth < needs {
+            return Err(context(format!("stack underflow at {}", offset)));
+        }
+        let depth = depth - needs + leaves;
+        let next = offset + length;
+
+        match op {
+            OpCode::Return => {}
+            OpCode::Jump => pending.push((next + u16_at(offset + 1), depth)),
+            OpCode::Loop => pending.push((next - u16_at(offset + 1), depth)),
+            OpCode::JumpIfFalse => {
+                pending.push((next + u16_at(offset + 1), depth));
+                pending.push((next, depth));
+            }
+            _ => pending.push((next, depth)),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rlox::{Backend, Rlox};
+
+    fn compile(source: &str) -> Module {
+        Rlox::with_backend(Backend::Vm)
+            .compile(source, "test.lox")
+            .unwrap_or_else(|_| panic!("{:?} should compile", source))
+    }
+
+    /// A module whose script is just `code`, with a valid header.
+    fn module(code: &[u8]) -> Vec<u8> {
+        let script = FunctionProto {
+            name: "script".to_string(),
+            arity: 0,
+            upvalue_count: 0,
+            chunk: Chunk {
+                code: code.to_vec(),
+                ..Chunk::default()
+            },
+        };
+        Module {
+            source_name: "test.lox".to_string(),
+            script: Rc::new(script),
+        }
+        .to_bytes()
+    }
+
+    /// Rewrites the header checksum after the payload was tampered with.
+    fn reseal(bytes: &mut [u8]) {
+        let sum = checksum(&bytes[HEADER_LENGTH..]);
+        bytes[MAGIC.len() + 2..HEADER_LENGTH].copy_from_slice(&sum.to_le_bytes());
+    }
+
+    fn malformed(bytes: &[u8]) -> String {
+        match Module::from_bytes(bytes) {
+            Err(LoadError::Malformed(message)) => message,
+            Err(error) => panic!("expected a malformed module, got {:?}", error),
+            Ok(_) => panic!("expected a malformed module, but it loaded"),
+        }
+    }
+
+    const NIL: u8 = OpCode::Nil as u8;
+    const POP: u8 = OpCode::Pop as u8;
+    const GET_LOCAL: u8 = OpCode::GetLocal as u8;
+    const JUMP_IF_FALSE: u8 = OpCode::JumpIfFalse as u8;
+    const RETURN: u8 = OpCode::Return as u8;
+
+    #[test]
+    fn round_trips_a_compiled_script() {
+        let original = compile(
+            "class A { init(x) { this.x = x; } get() { return this.x; } }
+             class B < A { get() { return super.get() * 2; } }
+             fn counter() { var n = 0; fn next() { n = n + 1; return n; } return next; }
+             var c = counter();
+             for (var i = 0; i < 3; i = i + 1) { if (i > 1) print c(); else print B(i).get(); }
+             print \"done\";",
+        );
+        let bytes = original.to_bytes();
+
+        let loaded = Module::from_bytes(&bytes).expect("the module should load");
+        assert_eq!(loaded.source_name, "test.lox");
+        assert_eq!(loaded.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn rejects_a_bad_magic_number() {
+        let mut bytes = compile("print 1;").to_bytes();
+        bytes[0] = b'X';
+        assert_eq!(
+            Module::from_bytes(&bytes).err(),
+            Some(LoadError::NotAModule)
+        );
+        assert_eq!(Module::from_bytes(b"RL").err(), Some(LoadError::NotAModule));
+    }
+
+    #[test]
+    fn rejects_another_format_version() {
+        let mut bytes = compile("print 1;").to_bytes();
+        bytes[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert_eq!(
+            Module::from_bytes(&bytes).err(),
+            Some(LoadError::VersionMismatch {
+                found: FORMAT_VERSION + 1,
+                expected: FORMAT_VERSION,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_a_checksum_mismatch() {
+        let mut bytes = compile("print 1;").to_bytes();
+        *bytes.last_mut().unwrap() ^= 0xFF;
+        assert_eq!(
+            Module::from_bytes(&bytes).err(),
+            Some(LoadError::ChecksumMismatch)
+        );
+    }
+
+    #[test]
+    fn rejects_a_truncated_module() {
+        let bytes = compile("print 1;").to_bytes();
+        assert_eq!(
+            Module::from_bytes(&bytes[..MAGIC.len() + 3]).err(),
+            Some(LoadError::Truncated)
+        );
+
+        let mut cut = bytes[..bytes.len() - 4].to_vec();
+        reseal(&mut cut);
+        assert_eq!(Module::from_bytes(&cut).err(), Some(LoadError::Truncated));
+    }
+
+    #[test]
+    fn rejects_a_local_slot_out_of_range() {
+        let message = malformed(&module(&[GET_LOCAL, 0xFF, 0xFF, RETURN]));
+        assert!(
+            message.contains("local slot 65535 out of range"),
+            "{}",
+            message
+        );
+
+        // Slot 0 always exists: it holds the running function.
+        assert!(Module::from_bytes(&module(&[GET_LOCAL, 0, 0, RETURN])).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_stack_underflow() {
+        let message = malformed(&module(&[POP, POP, NIL, RETURN]));
+        assert!(message.contains("stack underflow at 1"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_functions_nested_too_deeply() {
+        // Each function's only constant is the next one in.
+        let function = |constants| {
+            Rc::new(FunctionProto {
+                name: "f".to_string(),
+                arity: 0,
+                upvalue_count: 0,
+                chunk: Chunk {
+                    code: vec![NIL, RETURN],
+                    constants,
+                    ..Chunk::default()
+                },
+            })
+        };
+        let nested = |depth: usize| {
+            let mut script = function(vec![]);
+            for _ in 1..depth {
+                script = function(vec![Constant::Function(script)]);
+            }
+            Module {
+                source_name: "test.lox".to_string(),
+                script,
+            }
+            .to_bytes()
+        };
+
+        assert!(Module::from_bytes(&nested(MAX_NESTING)).is_ok());
+        let message = malformed(&nested(MAX_NESTING + 1));
+        assert!(message.contains("nested more than 256 deep"), "{}", message);
+    }
+
+    #[test]
+    fn rejects_paths_that_disagree_on_the_stack_depth() {
+        // Only the fall-through path pushes the extra nil before the join.
+        let message = malformed(&module(&[NIL, JUMP_IF_FALSE, 0, 1, NIL, RETURN]));
+        assert!(message.contains("different stack depths"), "{}", message);
+    }
+}
@@ -1,6 +1,6 @@
 use std::{collections::HashMap, error::Error, fmt::Display, sync::LazyLock};
 
-use crate::token::{Literal, Token, TokenType};
+use crate::token::{Literal, Span, Token, TokenType};
 
 static KEYWORDS: LazyLock<HashMap<&'static str, TokenType>> = LazyLock::new(|| {
     let mut keywords: HashMap<&str, TokenType> = HashMap::new();
@@ -27,22 +27,34 @@ static KEYWORDS: LazyLock<HashMap<&'static str, TokenType>> = LazyLock::new(|| {
 
 pub struct Scanner {
     source: Vec<char>,
+    /// Byte offset of every character in `source`, plus one past the end.
+    offsets: Vec<usize>,
     tokens: Vec<Token>,
     start: usize,
     current: usize,
     line: usize,
+    /// Index of the first character on the current line.
+    line_start: usize,
+    start_line: usize,
+    start_column: usize,
     errors: Vec<ScanError>,
 }
 
 #[derive(Clone, Debug)]
 pub struct ScanError {
+    pub code: &'static str,
     pub line: usize,
     pub message: String,
+    pub span: Span,
 }
 
 impl Display for ScanError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "[line {}] {}", self.line, self.message)
+        write!(
+            f,
+            "[line {}:{}] {}",
+            self.line, self.span.column, self.message
+        )
     }
 }
 
@@ -50,27 +62,64 @@ impl Error for ScanError {}
 
 impl Scanner {
     pub fn new(source: String) -> Self {
+        let offsets = source
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain(std::iter::once(source.len()))
+            .collect();
+
         Scanner {
             source: source.chars().collect(),
+            offsets,
             tokens: Vec::new(),
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            start_line: 1,
+            start_column: 1,
             errors: Vec::new(),
         }
     }
 
-    fn error(&mut self, line: usize, message: &str) {
+    fn error(&mut self, code: &'static str, line: usize, message: &str) {
+        let span = self.current_span();
         self.errors.push(ScanError {
+            code,
             line,
             message: message.to_string(),
+            span,
         })
     }
 
+    /// The span from the start of the current lexeme to the current character.
+    fn current_span(&self) -> Span {
+        Span {
+            start: self.offsets[self.start],
+            end: self.offsets[self.current],
+            line: self.start_line,
+            column: self.start_column,
+            end_line: self.line,
+            end_column: self.current - self.line_start + 1,
+        }
+    }
+
+    fn begin_lexeme(&mut self) {
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.current - self.line_start + 1;
+    }
+
+    fn newline(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
+    }
+
     fn add_token(&mut self, token_type: TokenType, literal: Option<Literal>) {
         let lexeme: String = self.source[self.start..self.current].iter().collect();
 
-        let token = Token::new(token_type, lexeme, literal, self.line);
+        let token =
+            Token::new(token_type, lexeme, literal, self.line).with_span(self.current_span());
 
         self.tokens.push(token);
     }
@@ -81,7 +130,8 @@ impl Scanner {
         literal: Option<Literal>,
         lexeme: impl Into<String>,
     ) {
-        let token = Token::new(token_type, lexeme, literal, self.line);
+        let token =
+            Token::new(token_type, lexeme, literal, self.line).with_span(self.current_span());
 
         self.tokens.push(token);
     }
@@ -89,7 +139,7 @@ impl Scanner {
     fn advance(&mut self) -> char {
         let char = self.source[self.current];
         self.current += 1;
-        return char;
+        char
     }
 
     fn is_at_end(&self) -> bool {
@@ -100,14 +150,14 @@ impl Scanner {
         if self.is_at_end() {
             return '\0';
         }
-        return self.source[self.current];
+        self.source[self.current]
     }
 
     fn peek_next(&self) -> char {
         if self.current + 1 >= self.source.len() {
             return '\0';
         }
-        return self.source[self.current + 1];
+        self.source[self.current + 1]
     }
 
     fn consume_if(&mut self, expected: char) -> bool {
@@ -120,21 +170,19 @@ impl Scanner {
         }
 
         self.advance();
-        return true;
+        true
     }
 
     // TODO: add escape symbosl support
     fn string(&mut self) {
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
+            if self.advance() == '\n' {
+                self.newline();
             }
-
-            self.advance();
         }
 
         if self.is_at_end() {
-            self.error(self.line, "Unterminated string");
+            self.error("S0002", self.line, "Unterminated string");
             return;
         }
 
@@ -205,10 +253,6 @@ impl Scanner {
         let mut depth = 1;
 
         while !self.is_at_end() && depth != 0 {
-            if self.peek() == '\n' {
-                self.line += 1;
-            }
-
             if self.peek() == '/' && self.peek_next() == '*' {
                 depth += 1;
                 self.advance();
@@ -223,12 +267,13 @@ impl Scanner {
                 continue;
             }
 
-            self.advance();
+            if self.advance() == '\n' {
+                self.newline();
+            }
         }
 
         if depth != 0 {
-            self.error(self.line, "Unterminated block comment");
-            return;
+            self.error("S0003", self.line, "Unterminated block comment");
         }
     }
 
@@ -292,9 +337,7 @@ impl Scanner {
                 // Ignoring whitespace
             }
 
-            '\n' => {
-                self.line += 1;
-            }
+            '\n' => self.newline(),
 
             '"' => self.string(),
 
@@ -305,22 +348,27 @@ impl Scanner {
             '?' => self.add_token(TokenType::Question, None),
             ':' => self.add_token(TokenType::Colon, None),
 
-            _ => self.error(self.line, &format!("Unexpected character: {}", char)),
+            _ => self.error(
+                "S0001",
+                self.line,
+                &format!("Unexpected character: {}", char),
+            ),
         }
     }
 
     pub fn scan_tokens(&mut self) -> Result<Vec<Token>, Vec<ScanError>> {
         while !self.is_at_end() {
-            self.start = self.current;
+            self.begin_lexeme();
             self.scan_token();
         }
 
-        if self.errors.len() > 0 {
+        if !self.errors.is_empty() {
             return Err(self.errors.clone());
         }
 
+        self.begin_lexeme();
         self.add_token_with_lexeme(TokenType::EOF, None, "");
 
-        return Ok(self.tokens.clone());
+        Ok(self.tokens.clone())
     }
 }
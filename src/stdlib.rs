@@ -0,0 +1,178 @@
+//! Native functions every script can call.
+//!
+//! Natives that reach outside the script — files, environment variables and
+//! processes — only work when the [`Capabilities`] of the [`Rlox`] grant
+//! them, and raise [`RuntimeErrorKind::PermissionDenied`] otherwise.
+
+use std::{
+    cell::RefCell,
+    env,
+    fmt::{self, Display},
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{interpreter::RuntimeErrorKind, rlox::Rlox, value::Value};
+
+/// What scripts are allowed to do outside of themselves. The default grants
+/// nothing.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Directory whose files `read_file` may read.
+    pub fs_read: Option<PathBuf>,
+    /// Directory whose files `write_file` may create or overwrite.
+    pub fs_write: Option<PathBuf>,
+    /// Whether `getenv` may read environment variables.
+    pub env: bool,
+    /// Whether `system` may run shell commands.
+    pub process: bool,
+}
+
+/// One of the [`Capabilities`], named in permission errors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capability {
+    FsRead,
+    FsWrite,
+    Env,
+    Process,
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Capability::FsRead => "reading files",
+            Capability::FsWrite => "writing files",
+            Capability::Env => "reading environment variables",
+            Capability::Process => "running processes",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Registers the natives on `rlox`. They consult `capabilities` on every
+/// call, so changes to it apply to scripts already loaded.
+pub(crate) fn install(rlox: &mut Rlox, capabilities: Rc<RefCell<Capabilities>>) {
+    rlox.register_fn("clock", || {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |elapsed| elapsed.as_secs_f64())
+    });
+
+    let granted = Rc::clone(&capabilities);
+    rlox.register_fn("read_file", move |path: String| {
+        let path = confine(
+            granted.borrow().fs_read.as_deref(),
+            Path::new(&path),
+            Capability::FsRead,
+        )?;
+        fs::read_to_string(&path).map_err(|error| io_error("read", &path, error))
+    });
+
+    let granted = Rc::clone(&capabilities);
+    rlox.register_fn("write_file", move |path: String, contents: String| {
+        let granted = granted.borrow();
+        let root = granted.fs_write.as_deref();
+        let path = confine(root, Path::new(&path), Capability::FsWrite)?;
+        let write = |error| io_error("write", &path, error);
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(write)?;
+        // A link swapped in since `confine` would have sent the file
+        // elsewhere, so check where it ended up before changing it.
+        confine(root, &path, Capability::FsWrite)?;
+        file.set_len(0).map_err(write)?;
+        file.write_all(contents.as_bytes()).map_err(write)?;
+        Ok(Value::Nil)
+    });
+
+    let granted = Rc::clone(&capabilities);
+    rlox.register_fn("getenv", move |name: String| {
+        require(granted.borrow().env, Capability::Env)?;
+        Ok(env::var(name).map_or(Value::Nil, Value::from))
+    });
+
+    let granted = capabilities;
+    rlox.register_fn("system", move |command: String| {
+        require(granted.borrow().process, Capability::Process)?;
+        let output = shell(&command).output().map_err(|error| {
+            RuntimeErrorKind::native(format!("Could not run command: {}.", error))
+        })?;
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    });
+}
+
+fn require(granted: bool, capability: Capability) -> Result<(), RuntimeErrorKind> {
+    if granted {
+        Ok(())
+    } else {
+        Err(RuntimeErrorKind::PermissionDenied { capability })
+    }
+}
+
+/// Resolves `path` and checks that it lies inside `root`. Symbolic links are
+/// followed first, so they can't be used to escape it.
+fn confine(
+    root: Option<&Path>,
+    path: &Path,
+    capability: Capability,
+) -> Result<PathBuf, RuntimeErrorKind> {
+    let denied = RuntimeErrorKind::PermissionDenied { capability };
+    let root = root.ok_or_else(|| denied.clone())?;
+
+    match (fs::canonicalize(root), resolve(path)) {
+        (Ok(root), Ok(path)) if path.starts_with(&root) => Ok(path),
+        _ => Err(denied),
+    }
+}
+
+/// Canonicalizes `path`, which may name a file that doesn't exist yet in an
+/// existing directory. A dangling symbolic link is an error, since where it
+/// leads can't be known until something creates its target.
+fn resolve(path: &Path) -> io::Result<PathBuf> {
+    match fs::canonicalize(path) {
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            if fs::symlink_metadata(path).is_ok() {
+                return Err(error);
+            }
+
+            let name = path.file_name().ok_or(error)?;
+            let parent = match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            Ok(fs::canonicalize(parent)?.join(name))
+        }
+        result => result,
+    }
+}
+
+fn io_error(action: &str, path: &Path, error: io::Error) -> RuntimeErrorKind {
+    RuntimeErrorKind::native(format!(
+        "Could not {} \"{}\": {}.",
+        action,
+        path.display(),
+        error
+    ))
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> process::Command {
+    let mut shell = process::Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> process::Command {
+    let mut shell = process::Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
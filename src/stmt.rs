@@ -1,16 +1,72 @@
+use std::rc::Rc;
+
 use crate::expr::Expr;
-use crate::token::Token;
+use crate::token::{Span, Token};
+
+pub struct FunctionDecl {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub body: Vec<Stmt>,
+}
 
 pub enum Stmt {
-	Var {
-		name: Token,
-		initializer: Box<Expr>,
-	},
-	Print {
-		expression: Box<Expr>,
-	},
-	Expression {
-		expression: Box<Expr>,
-	},
+    Var {
+        name: Token,
+        initializer: Box<Expr>,
+        span: Span,
+    },
+    Print {
+        expression: Box<Expr>,
+        span: Span,
+    },
+    Expression {
+        expression: Box<Expr>,
+        span: Span,
+    },
+    Block {
+        statements: Vec<Stmt>,
+        span: Span,
+    },
+    If {
+        condition: Box<Expr>,
+        then_branch: Box<Stmt>,
+        else_branch: Option<Box<Stmt>>,
+        span: Span,
+    },
+    While {
+        condition: Box<Expr>,
+        body: Box<Stmt>,
+        span: Span,
+    },
+    Function {
+        declaration: Rc<FunctionDecl>,
+        span: Span,
+    },
+    Return {
+        keyword: Token,
+        value: Option<Box<Expr>>,
+        span: Span,
+    },
+    Class {
+        name: Token,
+        superclass: Option<Box<Expr>>,
+        methods: Vec<Rc<FunctionDecl>>,
+        span: Span,
+    },
 }
 
+impl Stmt {
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Var { span, .. }
+            | Stmt::Print { span, .. }
+            | Stmt::Expression { span, .. }
+            | Stmt::Block { span, .. }
+            | Stmt::If { span, .. }
+            | Stmt::While { span, .. }
+            | Stmt::Function { span, .. }
+            | Stmt::Return { span, .. }
+            | Stmt::Class { span, .. } => *span,
+        }
+    }
+}
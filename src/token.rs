@@ -101,10 +101,6 @@ pub enum TokenType {
 }
 
 impl PartialEq for TokenType {
-    fn ne(&self, other: &Self) -> bool {
-        !self.eq(other)
-    }
-
     fn eq(&self, other: &Self) -> bool {
         core::mem::discriminant(self) == core::mem::discriminant(other)
     }
@@ -163,12 +159,39 @@ impl Display for TokenType {
     }
 }
 
+/// A range of source text. Offsets are in bytes and `end` is exclusive;
+/// lines and columns are 1-based, with columns counted in characters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Span {
+    /// Returns a span from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end,
+            line: self.line,
+            column: self.column,
+            end_line: other.end_line,
+            end_column: other.end_column,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub literal: Option<Literal>,
     pub line: usize,
+    pub span: Span,
 }
 
 impl Token {
@@ -183,8 +206,18 @@ impl Token {
             lexeme: lexeme.into(),
             literal,
             line,
+            span: Span {
+                line,
+                end_line: line,
+                ..Span::default()
+            },
         }
     }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
 }
 
 impl Display for Token {
@@ -0,0 +1,283 @@
+use std::{
+    cell::RefCell,
+    fmt::{self, Display},
+    rc::Rc,
+};
+
+use crate::{
+    class::LoxClass,
+    function::LoxFunction,
+    heap::Trace,
+    instance::LoxInstance,
+    interpreter::RuntimeErrorKind,
+    rlox::Dialect,
+    token::{Literal, TokenType},
+};
+
+#[derive(Clone)]
+pub enum Value {
+    /// Strings are immutable, so copies share one allocation.
+    String(Rc<str>),
+    Number(f64),
+    Boolean(bool),
+    Nil,
+    Function(Rc<LoxFunction>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<RefCell<LoxInstance>>),
+}
+
+/// The runtime type of a [`Value`], used when reporting type errors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueType {
+    String,
+    Number,
+    Boolean,
+    Nil,
+    Function,
+    Class,
+    Instance,
+}
+
+impl Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ValueType::String => "string",
+            ValueType::Number => "number",
+            ValueType::Boolean => "boolean",
+            ValueType::Nil => "nil",
+            ValueType::Function => "function",
+            ValueType::Class => "class",
+            ValueType::Instance => "instance",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Value {
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            Value::String(_) => ValueType::String,
+            Value::Number(_) => ValueType::Number,
+            Value::Boolean(_) => ValueType::Boolean,
+            Value::Nil => ValueType::Nil,
+            Value::Function(_) => ValueType::Function,
+            Value::Class(_) => ValueType::Class,
+            Value::Instance(_) => ValueType::Instance,
+        }
+    }
+
+    /// Whether the value counts as true in a condition. Only `nil` and
+    /// `false` are falsy in strict Lox; the extended dialect adds `0`.
+    pub(crate) fn is_truthy(&self, dialect: Dialect) -> bool {
+        match self {
+            Value::Number(value) => dialect == Dialect::Strict || *value != 0.0,
+            Value::Boolean(value) => *value,
+            Value::String(_) => true,
+            Value::Nil => false,
+            Value::Function(_) | Value::Class(_) | Value::Instance(_) => true,
+        }
+    }
+
+    /// Applies a unary operator. Both backends evaluate operators through
+    /// here so they agree on every operand type.
+    pub(crate) fn unary(
+        self,
+        operator: &TokenType,
+        dialect: Dialect,
+    ) -> Result<Value, RuntimeErrorKind> {
+        match operator {
+            TokenType::Minus => match self {
+                Value::Number(value) => Ok(Value::Number(-value)),
+                value => Err(RuntimeErrorKind::TypeMismatch {
+                    expected: ValueType::Number,
+                    actual: value.value_type(),
+                }),
+            },
+            TokenType::Bang => Ok(Value::Boolean(!self.is_truthy(dialect))),
+            _ => Err(RuntimeErrorKind::InvalidOperator),
+        }
+    }
+
+    /// Applies a binary operator to `self` and `right`.
+    ///
+    /// Equality is defined for every pair of values. Strict Lox only orders
+    /// numbers and divides by zero as IEEE 754 does. The extended dialect also
+    /// orders two strings lexicographically, makes division by zero an error
+    /// and, when just one operand is a string, converts the other and joins
+    /// them for any arithmetic operator. `+` joins two strings in both.
+    pub(crate) fn binary(
+        self,
+        operator: &TokenType,
+        right: Value,
+        dialect: Dialect,
+    ) -> Result<Value, RuntimeErrorKind> {
+        Ok(match (self, right) {
+            (_, r) if *operator == TokenType::Comma => r,
+            (l, r) if *operator == TokenType::EqualEqual => Value::Boolean(l == r),
+            (l, r) if *operator == TokenType::BangEqual => Value::Boolean(l != r),
+            (Value::Number(l), Value::Number(r)) => match operator {
+                TokenType::GreaterEqual => Value::Boolean(l >= r),
+                TokenType::Greater => Value::Boolean(l > r),
+                TokenType::Less => Value::Boolean(l < r),
+                TokenType::LessEqual => Value::Boolean(l <= r),
+                TokenType::Plus => Value::Number(l + r),
+                TokenType::Minus => Value::Number(l - r),
+                TokenType::Star => Value::Number(l * r),
+                TokenType::Slash => {
+                    if r == 0.0 && dialect == Dialect::Extended {
+                        return Err(RuntimeErrorKind::DivisionByZero);
+                    }
+
+                    Value::Number(l / r)
+                }
+                _ => return Err(RuntimeErrorKind::InvalidOperator),
+            },
+            (_, _) if dialect == Dialect::Strict && is_numeric(operator) => {
+                return Err(RuntimeErrorKind::OperandsNotNumbers);
+            }
+            (Value::String(l), Value::String(r)) if is_comparison(operator) => {
+                Value::Boolean(match operator {
+                    TokenType::GreaterEqual => l >= r,
+                    TokenType::Greater => l > r,
+                    TokenType::Less => l < r,
+                    _ => l <= r,
+                })
+            }
+            (l, r) if is_comparison(operator) => {
+                return Err(RuntimeErrorKind::UnsupportedOperands {
+                    left: l.value_type(),
+                    right: r.value_type(),
+                });
+            }
+            (Value::String(l), Value::String(r)) if *operator == TokenType::Plus => {
+                Value::String(format!("{}{}", l, r).into())
+            }
+            (Value::String(l), r) if dialect == Dialect::Extended => {
+                Value::String(format!("{}{}", l, r).into())
+            }
+            (l, Value::String(r)) if dialect == Dialect::Extended => {
+                Value::String(format!("{}{}", l, r).into())
+            }
+            (l, r) => {
+                return Err(RuntimeErrorKind::UnsupportedOperands {
+                    left: l.value_type(),
+                    right: r.value_type(),
+                });
+            }
+        })
+    }
+}
+
+fn is_comparison(operator: &TokenType) -> bool {
+    matches!(
+        operator,
+        TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual
+    )
+}
+
+/// Operators strict Lox only defines between two numbers.
+fn is_numeric(operator: &TokenType) -> bool {
+    is_comparison(operator)
+        || matches!(
+            operator,
+            TokenType::Minus | TokenType::Star | TokenType::Slash
+        )
+}
+
+/// Lox equality: strings compare by content, functions, classes and
+/// instances by identity, and values of different types are never equal.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::String(l), Value::String(r)) => l == r,
+            (Value::Number(l), Value::Number(r)) => l == r,
+            (Value::Boolean(l), Value::Boolean(r)) => l == r,
+            (Value::Nil, Value::Nil) => true,
+            (Value::Function(l), Value::Function(r)) => Rc::ptr_eq(l, r),
+            (Value::Class(l), Value::Class(r)) => Rc::ptr_eq(l, r),
+            (Value::Instance(l), Value::Instance(r)) => Rc::ptr_eq(l, r),
+            _ => false,
+        }
+    }
+}
+
+impl Trace for Value {
+    fn trace(&self, visit: &mut dyn FnMut(*const ())) {
+        match self {
+            Value::Function(function) => visit(Rc::as_ptr(function) as *const ()),
+            Value::Class(class) => visit(Rc::as_ptr(class) as *const ()),
+            Value::Instance(instance) => visit(Rc::as_ptr(instance) as *const ()),
+            Value::String(_) | Value::Number(_) | Value::Boolean(_) | Value::Nil => {}
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(number: f64) -> Self {
+        Value::Number(number)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(boolean: bool) -> Self {
+        Value::Boolean(boolean)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(string: &str) -> Self {
+        Value::String(string.into())
+    }
+}
+
+impl From<String> for Value {
+    fn from(string: String) -> Self {
+        Value::String(string.into())
+    }
+}
+
+/// `()` is how natives without a meaningful result return `nil`.
+impl From<()> for Value {
+    fn from(_: ()) -> Self {
+        Value::Nil
+    }
+}
+
+impl From<Literal> for Value {
+    fn from(literal: Literal) -> Self {
+        match literal {
+            Literal::String(s) => Value::String(s.into()),
+            Literal::Number(n) => Value::Number(n),
+            Literal::Boolean(b) => Value::Boolean(b),
+            Literal::Nil => Value::Nil,
+        }
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::String(s) => write!(f, "String({:?})", s),
+            Value::Number(n) => write!(f, "Number({:?})", n),
+            Value::Boolean(b) => write!(f, "Boolean({:?})", b),
+            Value::Nil => write!(f, "Nil"),
+            Value::Function(function) => write!(f, "Function({})", function),
+            Value::Class(class) => write!(f, "Class({})", class),
+            Value::Instance(instance) => write!(f, "Instance({})", instance.borrow()),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::String(s) => write!(f, "{}", s),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Nil => write!(f, "nil"),
+            Value::Function(function) => write!(f, "{}", function),
+            Value::Class(class) => write!(f, "{}", class),
+            Value::Instance(instance) => write!(f, "{}", instance.borrow()),
+        }
+    }
+}
@@ -0,0 +1,689 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::{
+    chunk::{Constant, FunctionProto, OpCode},
+    class::LoxClass,
+    function::{FunctionBody, LoxFunction},
+    heap::{Heap, Trace},
+    instance::LoxInstance,
+    interpreter::{Output, RuntimeError, RuntimeErrorKind, StackFrame},
+    limits::{Budget, Limits},
+    rlox::Dialect,
+    token::{Token, TokenType},
+    value::Value,
+};
+
+/// A function prototype paired with the variables it captured.
+pub struct Closure {
+    pub function: Rc<FunctionProto>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+/// A captured variable. It points into the stack while the declaring
+/// function is running and owns the value once that slot is popped.
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+impl Trace for Closure {
+    fn trace(&self, visit: &mut dyn FnMut(*const ())) {
+        for upvalue in &self.upvalues {
+            visit(Rc::as_ptr(upvalue) as *const ());
+        }
+    }
+}
+
+impl Trace for Upvalue {
+    fn trace(&self, visit: &mut dyn FnMut(*const ())) {
+        if let Upvalue::Closed(value) = self {
+            value.trace(visit);
+        }
+    }
+}
+
+struct CallFrame {
+    closure: Rc<Closure>,
+    ip: usize,
+    /// Stack index of slot zero.
+    base: usize,
+}
+
+/// Stack-based virtual machine running bytecode from the [`Compiler`].
+///
+/// [`Compiler`]: crate::compiler::Compiler
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    /// Whether the bottom frame is a script rather than a function called by
+    /// the host. Scripts don't count towards the call depth.
+    in_script: bool,
+    globals: HashMap<Rc<str>, Value>,
+    /// Upvalues still pointing into the stack, in no particular order.
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    heap: Heap,
+    budget: Budget,
+    dialect: Dialect,
+    output: Output,
+}
+
+impl Vm {
+    pub fn new(output: Output) -> Self {
+        Vm {
+            stack: vec![],
+            frames: vec![],
+            in_script: false,
+            globals: HashMap::new(),
+            open_upvalues: vec![],
+            heap: Heap::new(),
+            budget: Budget::new(),
+            dialect: Dialect::Extended,
+            output,
+        }
+    }
+
+    pub fn set_dialect(&mut self, dialect: Dialect) {
+        self.dialect = dialect;
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.budget.set_limits(limits);
+    }
+
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    pub fn heap_mut(&mut self) -> &mut Heap {
+        &mut self.heap
+    }
+
+    /// Runs `script` and returns the value it returns, `nil` unless it ends
+    /// in a `return`.
+    pub fn interpret(&mut self, script: Rc<FunctionProto>) -> Result<Value, RuntimeError> {
+        let closure = self.heap.closure(Closure {
+            function: script,
+            upvalues: vec![],
+        });
+        let function = self
+            .heap
+            .function(LoxFunction::compiled(Rc::clone(&closure)));
+        self.stack.push(Value::Function(function));
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            base: 0,
+        });
+        self.in_script = true;
+
+        self.budget.start();
+        let result = self.run();
+
+        self.finish(result)
+    }
+
+    /// Resets the VM after a run that failed, recording on the error the
+    /// calls in progress.
+    fn finish(&mut self, result: Result<Value, RuntimeError>) -> Result<Value, RuntimeError> {
+        let in_script = self.in_script;
+        result.map_err(|mut error| {
+            error.backtrace = self
+                .frames
+                .iter()
+                .enumerate()
+                .rev()
+                .map(|(index, frame)| StackFrame {
+                    file: None,
+                    function: (!in_script || index > 0)
+                        .then(|| frame.closure.function.name.clone()),
+                    span: frame.closure.function.chunk.span_at(frame.ip - 1),
+                })
+                .collect();
+
+            // Closures that escaped before the error keep the values they
+            // captured rather than pointing into the discarded stack.
+            self.close_upvalues(0);
+            self.stack.clear();
+            self.frames.clear();
+            error
+        })
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames
+            .last()
+            .expect("the VM always runs inside a frame")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames
+            .last_mut()
+            .expect("the VM always runs inside a frame")
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame_mut();
+        let byte = frame.closure.function.chunk.code[frame.ip];
+        frame.ip += 1;
+        byte
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        let frame = self.frame_mut();
+        let value = frame.closure.function.chunk.read_u16(frame.ip);
+        frame.ip += 2;
+        value
+    }
+
+    fn read_constant(&mut self) -> Constant {
+        let index = self.read_u16() as usize;
+        self.frame().closure.function.chunk.constants[index].clone()
+    }
+
+    fn read_string(&mut self) -> Rc<str> {
+        match self.read_constant() {
+            Constant::String(string) => string,
+            constant => unreachable!("expected a string constant, got {:?}", constant),
+        }
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("the compiler balances the stack")
+    }
+
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    /// A token standing in for the source token of the instruction being
+    /// executed, so errors point at the same place as in the interpreter.
+    /// Outside of any code, as when the host calls a function, it has no
+    /// location.
+    fn token(&self, token_type: TokenType, lexeme: &str) -> Token {
+        let span = self
+            .frames
+            .last()
+            .map(|frame| frame.closure.function.chunk.span_at(frame.ip - 1))
+            .unwrap_or_default();
+        Token::new(token_type, lexeme.to_string(), None, span.line).with_span(span)
+    }
+
+    fn error(&self, kind: RuntimeErrorKind, token_type: TokenType, lexeme: &str) -> RuntimeError {
+        RuntimeError::new(kind, &self.token(token_type, lexeme))
+    }
+
+    /// Calls a function or class from outside any running code and returns
+    /// its result once it has finished.
+    pub fn invoke(&mut self, callee: Value, arguments: &[Value]) -> Result<Value, RuntimeError> {
+        self.push(callee);
+        self.stack.extend_from_slice(arguments);
+        self.in_script = false;
+        self.budget.start();
+
+        let result = self.call_value(arguments.len()).and_then(|()| {
+            if self.frames.is_empty() {
+                // Natives and classes without an initializer finish straight
+                // away, leaving their result on the stack.
+                Ok(self.pop())
+            } else {
+                self.run()
+            }
+        });
+
+        self.finish(result)
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.globals.get(name).cloned()
+    }
+
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.globals.insert(name.into(), value);
+    }
+
+    fn run(&mut self) -> Result<Value, RuntimeError> {
+        loop {
+            let byte = self.read_byte();
+            let op = OpCode::try_from(byte)
+                .unwrap_or_else(|byte| unreachable!("invalid opcode {}", byte));
+
+            self.budget
+                .step(&mut self.heap)
+                .map_err(|kind| self.error(kind, TokenType::Identifier, ""))?;
+
+            match op {
+                OpCode::Constant => {
+                    let value = match self.read_constant() {
+                        Constant::Number(number) => Value::Number(number),
+                        Constant::String(string) => Value::String(string),
+                        Constant::Function(_) => unreachable!("functions are loaded by Closure"),
+                    };
+                    self.push(value);
+                }
+                OpCode::Nil => self.push(Value::Nil),
+                OpCode::True => self.push(Value::Boolean(true)),
+                OpCode::False => self.push(Value::Boolean(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = self.frame().base + self.read_u16() as usize;
+                    self.push(self.stack[slot].clone());
+                }
+                OpCode::SetLocal => {
+                    let slot = self.frame().base + self.read_u16() as usize;
+                    self.stack[slot] = self.peek(0).clone();
+                }
+                OpCode::GetGlobal => {
+                    let name = self.read_string();
+                    let Some(value) = self.globals.get(&name) else {
+                        return Err(self.error(
+                            RuntimeErrorKind::UndefinedVariable,
+                            TokenType::Identifier,
+                            &name,
+                        ));
+                    };
+                    self.push(value.clone());
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.read_string();
+                    let value = self.pop();
+                    self.globals.insert(name, value);
+                }
+                OpCode::SetGlobal => {
+                    let name = self.read_string();
+                    let value = self.peek(0).clone();
+                    let Some(slot) = self.globals.get_mut(&name) else {
+                        return Err(self.error(
+                            RuntimeErrorKind::UndefinedVariable,
+                            TokenType::Identifier,
+                            &name,
+                        ));
+                    };
+                    *slot = value;
+                }
+                OpCode::GetUpvalue => {
+                    let index = self.read_u16() as usize;
+                    let upvalue = Rc::clone(&self.frame().closure.upvalues[index]);
+                    let value = match &*upvalue.borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    self.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let index = self.read_u16() as usize;
+                    let upvalue = Rc::clone(&self.frame().closure.upvalues[index]);
+                    let value = self.peek(0).clone();
+                    match &mut *upvalue.borrow_mut() {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                OpCode::GetProperty => {
+                    let name = self.read_string();
+                    let token = self.token(TokenType::Identifier, &name);
+                    let value = match self.pop() {
+                        Value::Instance(instance) => {
+                            LoxInstance::get(&instance, &token, &mut self.heap)?
+                        }
+                        value => {
+                            return Err(RuntimeError::new(
+                                RuntimeErrorKind::NotAnInstance {
+                                    actual: value.value_type(),
+                                },
+                                &token,
+                            ));
+                        }
+                    };
+                    self.push(value);
+                }
+                OpCode::SetProperty => {
+                    let name = self.read_string();
+                    let token = self.token(TokenType::Identifier, &name);
+                    let value = self.pop();
+                    match self.pop() {
+                        Value::Instance(instance) => {
+                            instance.borrow_mut().set(&token, value.clone());
+                        }
+                        object => {
+                            return Err(RuntimeError::new(
+                                RuntimeErrorKind::NotAnInstance {
+                                    actual: object.value_type(),
+                                },
+                                &token,
+                            ));
+                        }
+                    }
+                    self.push(value);
+                }
+                OpCode::GetSuper => {
+                    let name = self.read_string();
+                    // The compiler always leaves a class over an instance
+                    // here, but a loaded module might not.
+                    let superclass = match self.pop() {
+                        Value::Class(superclass) => superclass,
+                        value => {
+                            let kind = RuntimeErrorKind::SuperclassNotClass {
+                                actual: value.value_type(),
+                            };
+                            return Err(self.error(kind, TokenType::Identifier, &name));
+                        }
+                    };
+                    let instance = match self.pop() {
+                        Value::Instance(instance) => instance,
+                        value => {
+                            let kind = RuntimeErrorKind::NotAnInstance {
+                                actual: value.value_type(),
+                            };
+                            return Err(self.error(kind, TokenType::Identifier, &name));
+                        }
+                    };
+
+                    match superclass.find_method(&name) {
+                        Some(method) => {
+                            let method = method.bind(instance, &mut self.heap);
+                            self.push(Value::Function(method));
+                        }
+                        None => {
+                            return Err(self.error(
+                                RuntimeErrorKind::UndefinedProperty,
+                                TokenType::Identifier,
+                                &name,
+                            ));
+                        }
+                    }
+                }
+                OpCode::Equal
+                | OpCode::NotEqual
+                | OpCode::Greater
+                | OpCode::GreaterEqual
+                | OpCode::Less
+                | OpCode::LessEqual
+                | OpCode::Add
+                | OpCode::Subtract
+                | OpCode::Multiply
+                | OpCode::Divide
+                | OpCode::Comma => {
+                    let (token_type, lexeme) = operator(op);
+                    let right = self.pop();
+                    let left = self.pop();
+                    let value = left
+                        .binary(&token_type, right, self.dialect)
+                        .map_err(|kind| self.error(kind, token_type, lexeme))?;
+                    self.heap.string(&value);
+                    self.push(value);
+                }
+                OpCode::Not | OpCode::Negate => {
+                    let (token_type, lexeme) = operator(op);
+                    let value = self
+                        .pop()
+                        .unary(&token_type, self.dialect)
+                        .map_err(|kind| self.error(kind, token_type, lexeme))?;
+                    self.push(value);
+                }
+                OpCode::Print => {
+                    let value = self.pop();
+                    writeln!(self.output.borrow_mut(), "{}", value).map_err(|error| {
+                        self.error(
+                            RuntimeErrorKind::output_failed(error),
+                            TokenType::Print,
+                            "print",
+                        )
+                    })?;
+                }
+                OpCode::Jump => {
+                    let offset = self.read_u16() as usize;
+                    self.frame_mut().ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_u16() as usize;
+                    if !self.peek(0).is_truthy(self.dialect) {
+                        self.frame_mut().ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = self.read_u16() as usize;
+                    self.frame_mut().ip -= offset;
+                }
+                OpCode::Call => {
+                    let argument_count = self.read_byte() as usize;
+                    self.call_value(argument_count)?;
+                }
+                OpCode::Closure => {
+                    let Constant::Function(function) = self.read_constant() else {
+                        unreachable!("Closure always refers to a function constant");
+                    };
+
+                    let mut upvalues = Vec::with_capacity(function.upvalue_count);
+                    for _ in 0..function.upvalue_count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_u16() as usize;
+
+                        upvalues.push(if is_local {
+                            self.capture_upvalue(self.frame().base + index)
+                        } else {
+                            Rc::clone(&self.frame().closure.upvalues[index])
+                        });
+                    }
+
+                    let closure = self.heap.closure(Closure { function, upvalues });
+                    let function = self.heap.function(LoxFunction::compiled(closure));
+                    self.push(Value::Function(function));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().expect("returning from a frame");
+                    self.close_upvalues(frame.base);
+                    self.stack.truncate(frame.base);
+
+                    if self.frames.is_empty() {
+                        return Ok(result);
+                    }
+
+                    self.push(result);
+                }
+                OpCode::Class => {
+                    let name = self.read_string();
+                    let method_count = self.read_u16() as usize;
+                    let has_superclass = self.read_byte() == 1;
+
+                    let mut methods = HashMap::new();
+                    for method in self.stack.split_off(self.stack.len() - method_count) {
+                        match method {
+                            Value::Function(method) => {
+                                methods.insert(method.name().to_string(), method);
+                            }
+                            value => {
+                                let kind = RuntimeErrorKind::NotCallable {
+                                    actual: value.value_type(),
+                                };
+                                return Err(self.error(kind, TokenType::Identifier, &name));
+                            }
+                        }
+                    }
+
+                    let superclass = if has_superclass {
+                        match self.peek(0) {
+                            Value::Class(superclass) => Some(Rc::clone(superclass)),
+                            value => {
+                                return Err(self.error(
+                                    RuntimeErrorKind::SuperclassNotClass {
+                                        actual: value.value_type(),
+                                    },
+                                    TokenType::Identifier,
+                                    &name,
+                                ));
+                            }
+                        }
+                    } else {
+                        None
+                    };
+
+                    let class = LoxClass::new(name.to_string(), superclass, methods);
+                    let class = self.heap.class(class);
+                    self.push(Value::Class(class));
+                }
+            }
+        }
+    }
+
+    fn call_value(&mut self, argument_count: usize) -> Result<(), RuntimeError> {
+        let base = self.stack.len() - 1 - argument_count;
+
+        match self.stack[base].clone() {
+            Value::Function(function) => self.call(&function, base, argument_count),
+            Value::Class(class) => {
+                self.check_arity(class.arity(), argument_count)?;
+
+                let instance = self.heap.instance(LoxInstance::new(Rc::clone(&class)));
+                self.stack[base] = Value::Instance(Rc::clone(&instance));
+
+                match class.find_method("init") {
+                    Some(initializer) => {
+                        let initializer = initializer.bind(instance, &mut self.heap);
+                        self.call(&initializer, base, argument_count)
+                    }
+                    None => self.enter_call(),
+                }
+            }
+            value => Err(self.error(
+                RuntimeErrorKind::NotCallable {
+                    actual: value.value_type(),
+                },
+                TokenType::RightParen,
+                ")",
+            )),
+        }
+    }
+
+    fn call(
+        &mut self,
+        function: &LoxFunction,
+        base: usize,
+        argument_count: usize,
+    ) -> Result<(), RuntimeError> {
+        let (closure, receiver) = match function.body() {
+            FunctionBody::Compiled { closure, receiver } => (closure, receiver),
+            FunctionBody::Native {
+                arity, function, ..
+            } => {
+                self.check_arity(*arity, argument_count)?;
+                self.enter_call()?;
+
+                let result = function(&self.stack[base + 1..])
+                    .map_err(|kind| self.error(kind, TokenType::RightParen, ")"))?;
+                self.heap.string(&result);
+                self.stack.truncate(base);
+                self.push(result);
+                return Ok(());
+            }
+            FunctionBody::Interpreted { .. } => {
+                return Err(self.error(
+                    RuntimeErrorKind::ForeignFunction,
+                    TokenType::RightParen,
+                    ")",
+                ));
+            }
+        };
+
+        self.check_arity(closure.function.arity, argument_count)?;
+        self.enter_call()?;
+
+        if let Some(receiver) = receiver {
+            self.stack[base] = Value::Instance(Rc::clone(receiver));
+        }
+
+        self.frames.push(CallFrame {
+            closure: Rc::clone(closure),
+            ip: 0,
+            base,
+        });
+
+        Ok(())
+    }
+
+    /// Checks that one more call may start. Like the tree-walker, this
+    /// counts the calls in progress once it has, but not the script.
+    fn enter_call(&self) -> Result<(), RuntimeError> {
+        let depth = self.frames.len() - usize::from(self.in_script) + 1;
+        self.budget
+            .enter_call(depth)
+            .map_err(|kind| self.error(kind, TokenType::RightParen, ")"))
+    }
+
+    fn check_arity(&self, arity: usize, argument_count: usize) -> Result<(), RuntimeError> {
+        if arity == argument_count {
+            return Ok(());
+        }
+
+        Err(self.error(
+            RuntimeErrorKind::ArityMismatch {
+                expected: arity,
+                actual: argument_count,
+            },
+            TokenType::RightParen,
+            ")",
+        ))
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let existing = self
+            .open_upvalues
+            .iter()
+            .find(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(open) if open == slot));
+
+        if let Some(upvalue) = existing {
+            return Rc::clone(upvalue);
+        }
+
+        let upvalue = self.heap.upvalue(Upvalue::Open(slot));
+        self.open_upvalues.push(Rc::clone(&upvalue));
+        upvalue
+    }
+
+    /// Moves every captured variable living at or above `from` off the stack.
+    fn close_upvalues(&mut self, from: usize) {
+        let stack = &self.stack;
+
+        self.open_upvalues.retain(|upvalue| {
+            let mut upvalue = upvalue.borrow_mut();
+            match *upvalue {
+                Upvalue::Open(slot) if slot >= from => {
+                    *upvalue = Upvalue::Closed(stack[slot].clone());
+                    false
+                }
+                _ => true,
+            }
+        });
+    }
+}
+
+/// The token type and lexeme of the source operator an opcode was compiled
+/// from.
+fn operator(op: OpCode) -> (TokenType, &'static str) {
+    match op {
+        OpCode::Equal => (TokenType::EqualEqual, "=="),
+        OpCode::NotEqual => (TokenType::BangEqual, "!="),
+        OpCode::Greater => (TokenType::Greater, ">"),
+        OpCode::GreaterEqual => (TokenType::GreaterEqual, ">="),
+        OpCode::Less => (TokenType::Less, "<"),
+        OpCode::LessEqual => (TokenType::LessEqual, "<="),
+        OpCode::Add => (TokenType::Plus, "+"),
+        OpCode::Subtract => (TokenType::Minus, "-"),
+        OpCode::Multiply => (TokenType::Star, "*"),
+        OpCode::Divide => (TokenType::Slash, "/"),
+        OpCode::Comma => (TokenType::Comma, ","),
+        OpCode::Not => (TokenType::Bang, "!"),
+        OpCode::Negate => (TokenType::Minus, "-"),
+        _ => unreachable!("{:?} is not an operator", op),
+    }
+}
@@ -0,0 +1,776 @@
+//! Tests of the library API used to embed rlox in a host program.
+
+use std::{cell::RefCell, env, fs, io, process, rc::Rc, thread, time::Duration};
+
+use rlox::{
+    Backend, Capabilities, Capability, Dialect, GcConfig, Limits, Module, Rlox, RloxError,
+    RuntimeError, RuntimeErrorKind, Value, token::TokenType,
+};
+
+const BACKENDS: [Backend; 2] = [Backend::TreeWalker, Backend::Vm];
+
+/// A sink the test can read back after handing it to [`Rlox`].
+#[derive(Clone, Default)]
+struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+impl Buffer {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+impl io::Write for Buffer {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn eval_returns_the_last_expression() {
+    for backend in BACKENDS {
+        let mut rlox = Rlox::with_backend(backend);
+
+        assert_eq!(rlox.eval("1 + 2;").unwrap(), Value::Number(3.0));
+        assert_eq!(
+            rlox.eval("var s = \"a\"; s + \"b\";").unwrap(),
+            Value::String("ab".into())
+        );
+        assert_eq!(rlox.eval("var x = 1;").unwrap(), Value::Nil);
+    }
+}
+
+#[test]
+fn globals_are_shared_with_the_host() {
+    for backend in BACKENDS {
+        let mut rlox = Rlox::with_backend(backend);
+
+        rlox.set_global("limit", Value::Number(10.0));
+        rlox.run("var doubled = limit * 2;").unwrap();
+
+        assert_eq!(rlox.get_global("doubled"), Some(Value::Number(20.0)));
+        assert_eq!(rlox.get_global("missing"), None);
+    }
+}
+
+#[test]
+fn functions_outlive_the_program_that_declared_them() {
+    for backend in BACKENDS {
+        let mut rlox = Rlox::with_backend(backend);
+        rlox.run(
+            "fn adder(n) {
+               fn add(x) { var sum = x + n; return sum; }
+               return add;
+             }
+             class Pair {
+               init(a, b) { this.a = a; this.b = b; }
+               sum() { var a = this.a; return a + this.b; }
+             }",
+        )
+        .unwrap();
+
+        rlox.run("var add2 = adder(2);").unwrap();
+        rlox.run("var pair = Pair(3, 4);").unwrap();
+
+        assert_eq!(rlox.eval("add2(5);").unwrap(), Value::Number(7.0));
+        assert_eq!(rlox.eval("pair.sum();").unwrap(), Value::Number(7.0));
+        assert_eq!(
+            rlox.eval("fn later() { var n = 10; return add2(n) + n; } later();")
+                .unwrap(),
+            Value::Number(22.0)
+        );
+        assert_eq!(
+            rlox.eval("fn twice(f, x) { return f(f(x)); } twice(add2, 1);")
+                .unwrap(),
+            Value::Number(5.0)
+        );
+    }
+}
+
+#[test]
+fn closures_escaping_a_failed_run_keep_their_captures() {
+    for backend in BACKENDS {
+        let mut rlox = Rlox::with_backend(backend);
+        let errors = rlox
+            .eval(
+                "var f;
+                 fn outer() { var x = 1; fn inner() { return x; } f = inner; nil + 1; }
+                 outer();",
+            )
+            .unwrap_err();
+        assert_eq!(runtime_error(errors).code(), "R0002");
+
+        assert_eq!(rlox.eval("f();").unwrap(), Value::Number(1.0));
+    }
+}
+
+#[test]
+fn functions_from_the_other_backend_are_errors() {
+    for (from, to) in [
+        (Backend::TreeWalker, Backend::Vm),
+        (Backend::Vm, Backend::TreeWalker),
+    ] {
+        let mut source = Rlox::with_backend(from);
+        let function = source.eval("fn f() { return 1; } f;").unwrap();
+        let class = source
+            .eval("class C { init() { this.x = 1; } } C;")
+            .unwrap();
+
+        let mut target = Rlox::with_backend(to);
+        target.set_global("f", function);
+        target.set_global("C", class);
+
+        let error = target.call("f", &[]).unwrap_err();
+        assert_eq!(error.kind, RuntimeErrorKind::ForeignFunction);
+        let error = runtime_error(target.eval("f();").unwrap_err());
+        assert_eq!(error.kind, RuntimeErrorKind::ForeignFunction);
+        let error = runtime_error(target.eval("C();").unwrap_err());
+        assert_eq!(error.code(), "R0020");
+
+        // They still work on the backend that made them.
+        assert_eq!(source.eval("f();").unwrap(), Value::Number(1.0));
+    }
+}
+
+fn with_dialect(backend: Backend, dialect: Dialect) -> Rlox {
+    let mut rlox = Rlox::with_backend(backend);
+    rlox.set_dialect(dialect);
+    rlox
+}
+
+#[test]
+fn dialects_agree_on_reference_lox() {
+    for backend in BACKENDS {
+        for dialect in [Dialect::Strict, Dialect::Extended] {
+            let mut rlox = with_dialect(backend, dialect);
+
+            assert_eq!(rlox.eval("7 / 2;").unwrap(), Value::Number(3.5));
+            assert_eq!(rlox.eval("1 < 2;").unwrap(), Value::Boolean(true));
+            assert_eq!(
+                rlox.eval("\"a\" + \"b\";").unwrap(),
+                Value::String("ab".into())
+            );
+            assert_eq!(rlox.eval("!nil;").unwrap(), Value::Boolean(true));
+
+            let error = runtime_error(rlox.eval("1 + nil;").unwrap_err());
+            assert_eq!(error.code(), "R0002");
+        }
+    }
+}
+
+#[test]
+fn dialects_differ_where_reference_lox_does() {
+    for backend in BACKENDS {
+        let mut strict = with_dialect(backend, Dialect::Strict);
+        let mut extended = with_dialect(backend, Dialect::Extended);
+
+        // Division by zero.
+        assert_eq!(strict.eval("1 / 0;").unwrap(), Value::Number(f64::INFINITY));
+        let error = runtime_error(extended.eval("1 / 0;").unwrap_err());
+        assert_eq!(error.kind, RuntimeErrorKind::DivisionByZero);
+
+        // Ordering strings.
+        let error = runtime_error(strict.eval("\"a\" < \"b\";").unwrap_err());
+        assert_eq!(error.kind, RuntimeErrorKind::OperandsNotNumbers);
+        assert_eq!(error.message(), "Operands must be numbers.");
+        let error = runtime_error(strict.eval("\"b\" > 1;").unwrap_err());
+        assert_eq!(error.message(), "Operands must be numbers.");
+        assert_eq!(
+            extended.eval("\"a\" < \"b\";").unwrap(),
+            Value::Boolean(true)
+        );
+
+        // Arithmetic on strings.
+        let error = runtime_error(strict.eval("\"a\" - 1;").unwrap_err());
+        assert_eq!(error.message(), "Operands must be numbers.");
+        assert_eq!(
+            extended.eval("\"a\" + 1;").unwrap(),
+            Value::String("a1".into())
+        );
+
+        // Truthiness of zero.
+        assert_eq!(strict.eval("!0;").unwrap(), Value::Boolean(false));
+        assert_eq!(extended.eval("!0;").unwrap(), Value::Boolean(true));
+
+        // The conditional and comma operators.
+        assert!(matches!(
+            strict.eval("true ? 1 : 2;").unwrap_err().as_slice(),
+            [RloxError::Parse(_)]
+        ));
+        assert_eq!(extended.eval("true ? 1 : 2;").unwrap(), Value::Number(1.0));
+        assert!(strict.eval("1, 2;").is_err());
+        assert_eq!(extended.eval("1, 2;").unwrap(), Value::Number(2.0));
+    }
+}
+
+#[test]
+fn output_and_diagnostics_go_to_their_sinks() {
+    for backend in BACKENDS {
+        let output = Buffer::default();
+        let diagnostics = Buffer::default();
+        let mut rlox = Rlox::new(backend, output.clone(), diagnostics.clone());
+
+        let source = "print \"hello\";\nprint -nil;";
+        let errors = rlox.eval(source).unwrap_err();
+        rlox.report_errors(&errors, source, "test.rlox").unwrap();
+
+        assert_eq!(output.contents(), "hello\n");
+        assert!(matches!(errors.as_slice(), [RloxError::Runtime(_)]));
+        assert!(diagnostics.contents().contains("test.rlox:2:7"));
+    }
+}
+
+#[test]
+fn static_errors_are_returned_together() {
+    let mut rlox = Rlox::with_backend(Backend::TreeWalker);
+
+    let errors = rlox.eval("print ;\nvar = 1;").unwrap_err();
+
+    assert_eq!(errors.len(), 2);
+    assert!(
+        errors
+            .iter()
+            .all(|error| matches!(error, RloxError::Parse(_)))
+    );
+}
+
+#[test]
+fn tokens_and_ast_are_available_to_tools() {
+    let rlox = Rlox::with_backend(Backend::TreeWalker);
+
+    let tokens = rlox.tokens("var a = 1;").unwrap();
+    let types: Vec<TokenType> = tokens
+        .iter()
+        .map(|token| token.token_type.clone())
+        .collect();
+    assert_eq!(
+        types,
+        [
+            TokenType::Var,
+            TokenType::Identifier,
+            TokenType::Equal,
+            TokenType::Number,
+            TokenType::Semicolon,
+            TokenType::EOF,
+        ]
+    );
+
+    assert_eq!(rlox.parse("var a = 1; print a;").unwrap().len(), 2);
+}
+
+fn runtime_error(errors: Vec<RloxError>) -> RuntimeError {
+    match errors.as_slice() {
+        [RloxError::Runtime(error)] => error.clone(),
+        _ => panic!("expected a single runtime error"),
+    }
+}
+
+#[test]
+fn natives_are_callable_from_scripts() {
+    for backend in BACKENDS {
+        let output = Buffer::default();
+        let mut rlox = Rlox::new(backend, output.clone(), io::sink());
+        rlox.register_native("now", 0, |_| Ok(Value::Number(1234.0)));
+        rlox.register_fn("greet", |name: String, excited: bool| {
+            format!("hello {}{}", name, if excited { "!" } else { "" })
+        });
+
+        rlox.run("print now(); print now; print greet(\"lox\", true);")
+            .unwrap();
+
+        assert_eq!(output.contents(), "1234\n<native fn now>\nhello lox!\n");
+    }
+}
+
+#[test]
+fn natives_report_errors_at_the_call() {
+    for backend in BACKENDS {
+        let mut rlox = Rlox::with_backend(backend);
+        rlox.register_fn("half", |x: f64| {
+            if x.fract() != 0.0 {
+                return Err(RuntimeErrorKind::native("half() takes an integer."));
+            }
+            Ok(x / 2.0)
+        });
+
+        assert_eq!(rlox.eval("half(8);").unwrap(), Value::Number(4.0));
+
+        let error = runtime_error(rlox.eval("half(1.5);").unwrap_err());
+        assert_eq!(error.message(), "half() takes an integer.");
+        assert_eq!(error.span().column, 9);
+
+        let error = runtime_error(rlox.eval("\n half(\"8\");").unwrap_err());
+        assert_eq!(error.message(), "Argument 1 must be a number, got string.");
+        assert_eq!(error.line(), 2);
+
+        let error = runtime_error(rlox.eval("half();").unwrap_err());
+        assert_eq!(error.message(), "Expected 1 arguments but got 0.");
+    }
+}
+
+#[test]
+fn host_calls_script_functions() {
+    for backend in BACKENDS {
+        let output = Buffer::default();
+        let mut rlox = Rlox::new(backend, output.clone(), io::sink());
+        rlox.run(
+            "var events = 0;
+             fn on_event(n) { events = events + n; print \"event\"; return events; }
+             fn make_counter() {
+               var count = 0;
+               fn counter() { count = count + 1; return count; }
+               return counter;
+             }
+             var counter = make_counter();",
+        )
+        .unwrap();
+
+        assert_eq!(
+            rlox.call("on_event", &[Value::from(3.0)]).unwrap(),
+            Value::Number(3.0)
+        );
+        assert_eq!(
+            rlox.call("on_event", &[Value::from(4.0)]).unwrap(),
+            Value::Number(7.0)
+        );
+        assert_eq!(output.contents(), "event\nevent\n");
+        assert_eq!(rlox.get_global("events"), Some(Value::Number(7.0)));
+
+        rlox.call("counter", &[]).unwrap();
+        assert_eq!(rlox.call("counter", &[]).unwrap(), Value::Number(2.0));
+    }
+}
+
+#[test]
+fn host_inspects_instances() {
+    for backend in BACKENDS {
+        let mut rlox = Rlox::with_backend(backend);
+        rlox.run(
+            "class Point {
+               init(x, y) { this.x = x; this.y = y; }
+             }
+             class Empty {}",
+        )
+        .unwrap();
+
+        let Value::Instance(point) = rlox
+            .call("Point", &[Value::from(1.0), Value::from(2.0)])
+            .unwrap()
+        else {
+            panic!("expected an instance");
+        };
+        let point = point.borrow();
+        assert_eq!(point.class_name(), "Point");
+        assert_eq!(point.field("x"), Some(Value::Number(1.0)));
+        assert_eq!(point.field("z"), None);
+
+        let mut fields: Vec<(&str, &Value)> = point.fields().collect();
+        fields.sort_by_key(|(name, _)| *name);
+        assert_eq!(
+            fields,
+            [("x", &Value::Number(1.0)), ("y", &Value::Number(2.0))]
+        );
+
+        assert!(matches!(
+            rlox.call("Empty", &[]).unwrap(),
+            Value::Instance(_)
+        ));
+    }
+}
+
+#[test]
+fn host_calls_report_errors() {
+    for backend in BACKENDS {
+        let mut rlox = Rlox::with_backend(backend);
+        rlox.register_fn("half", |x: f64| x / 2.0);
+        rlox.run("fn fail(x) { return -x; } var number = 1;")
+            .unwrap();
+
+        assert_eq!(
+            rlox.call("half", &[Value::from(5.0)]).unwrap(),
+            Value::Number(2.5)
+        );
+
+        let error = rlox.call("missing", &[]).unwrap_err();
+        assert_eq!(error.message(), "Undefined variable \"missing\".");
+
+        let error = rlox.call("fail", &[]).unwrap_err();
+        assert_eq!(error.message(), "Expected 1 arguments but got 0.");
+
+        let error = rlox.call("fail", &[Value::from("x")]).unwrap_err();
+        assert_eq!(error.line(), 1);
+
+        assert!(rlox.call("number", &[]).is_err());
+
+        // A failed call leaves the backend usable.
+        assert_eq!(rlox.eval("number + 1;").unwrap(), Value::Number(2.0));
+    }
+}
+
+fn limited(backend: Backend, limits: Limits) -> Rlox {
+    let mut rlox = Rlox::with_backend(backend);
+    rlox.set_limits(limits);
+    rlox
+}
+
+#[test]
+fn limits_stop_runaway_scripts() {
+    for backend in BACKENDS {
+        let mut rlox = limited(
+            backend,
+            Limits {
+                max_steps: Some(1000),
+                ..Limits::default()
+            },
+        );
+        let error = runtime_error(rlox.eval("while (true) {}").unwrap_err());
+        assert_eq!(error.kind, RuntimeErrorKind::StepLimitExceeded);
+        // Each run gets a fresh allowance.
+        assert_eq!(rlox.eval("1 + 1;").unwrap(), Value::Number(2.0));
+
+        let mut rlox = limited(
+            backend,
+            Limits {
+                timeout: Some(Duration::from_millis(50)),
+                ..Limits::default()
+            },
+        );
+        let error = runtime_error(rlox.eval("for (;;) {}").unwrap_err());
+        assert_eq!(error.kind, RuntimeErrorKind::Timeout);
+        assert_eq!(error.message(), "Execution timed out.");
+
+        let mut rlox = limited(
+            backend,
+            Limits {
+                max_heap_bytes: Some(64 * 1024),
+                ..Limits::default()
+            },
+        );
+        rlox.run("class Node { init(next) { this.next = next; } }")
+            .unwrap();
+        // Garbage is collected rather than counted against the limit.
+        rlox.run("for (var i = 0; i < 5000; i = i + 1) Node(nil);")
+            .unwrap();
+        let error = runtime_error(
+            rlox.eval("var list = nil; for (;;) list = Node(list);")
+                .unwrap_err(),
+        );
+        assert_eq!(error.kind, RuntimeErrorKind::HeapLimitExceeded);
+    }
+}
+
+#[test]
+fn string_growth_counts_against_the_heap_limit() {
+    for backend in BACKENDS {
+        let mut rlox = limited(
+            backend,
+            Limits {
+                max_heap_bytes: Some(1024 * 1024),
+                ..Limits::default()
+            },
+        );
+
+        // Strings that are dropped again don't add up.
+        rlox.run("for (var i = 0; i < 2000; i = i + 1) { var s = \"ab\" + \"cd\"; }")
+            .unwrap();
+
+        rlox.run("var s = \"x\"; var doublings = 0;").unwrap();
+        let error = runtime_error(
+            rlox.eval("for (;;) { s = s + s; doublings = doublings + 1; }")
+                .unwrap_err(),
+        );
+        assert_eq!(error.kind, RuntimeErrorKind::HeapLimitExceeded);
+        // It stops around the first string of 2^20 bytes, not gigabytes later.
+        let Some(Value::Number(doublings)) = rlox.get_global("doublings") else {
+            panic!("doublings should be a number");
+        };
+        assert!((18.0..=20.0).contains(&doublings), "{}", doublings);
+    }
+}
+
+#[test]
+fn deep_recursion_is_a_stack_overflow() {
+    for backend in BACKENDS {
+        let mut rlox = limited(
+            backend,
+            Limits {
+                max_call_depth: 50,
+                ..Limits::default()
+            },
+        );
+        rlox.run("fn depth(n) { if (n == 0) return 0; return depth(n - 1) + 1; }")
+            .unwrap();
+
+        assert_eq!(
+            rlox.call("depth", &[Value::from(40.0)]).unwrap(),
+            Value::Number(40.0)
+        );
+
+        let error = rlox.call("depth", &[Value::from(60.0)]).unwrap_err();
+        assert_eq!(error.kind, RuntimeErrorKind::StackOverflow);
+        assert_eq!(error.message(), "Stack overflow.");
+        assert_eq!(error.code(), "R0015");
+
+        assert_eq!(rlox.eval("depth(10);").unwrap(), Value::Number(10.0));
+    }
+}
+
+#[test]
+fn both_backends_stop_at_the_same_call_depth() {
+    for backend in BACKENDS {
+        let mut rlox = limited(
+            backend,
+            Limits {
+                max_call_depth: 50,
+                ..Limits::default()
+            },
+        );
+        // `depth(n)` makes n + 1 calls, and `tick(n)` one more for the native.
+        rlox.run(
+            "fn depth(n) { if (n == 0) return 0; return depth(n - 1) + 1; }
+             fn tick(n) { if (n == 0) return clock(); return tick(n - 1); }",
+        )
+        .unwrap();
+
+        assert!(rlox.eval("depth(49);").is_ok(), "{:?}", backend);
+        assert!(rlox.call("depth", &[Value::from(49.0)]).is_ok());
+        assert!(rlox.eval("tick(48);").is_ok());
+
+        for error in [
+            runtime_error(rlox.eval("depth(50);").unwrap_err()),
+            rlox.call("depth", &[Value::from(50.0)]).unwrap_err(),
+            runtime_error(rlox.eval("tick(49);").unwrap_err()),
+        ] {
+            assert_eq!(error.kind, RuntimeErrorKind::StackOverflow, "{:?}", backend);
+        }
+    }
+}
+
+#[test]
+fn deep_recursion_fits_on_a_small_host_stack() {
+    // Far less stack than a debug build needs for a thousand Lox calls.
+    let host = thread::Builder::new().stack_size(256 * 1024);
+    let handle = host
+        .spawn(|| {
+            for backend in BACKENDS {
+                let mut rlox = limited(
+                    backend,
+                    Limits {
+                        max_call_depth: 2000,
+                        ..Limits::default()
+                    },
+                );
+                rlox.run("fn depth(n) { if (n == 0) return 0; return depth(n - 1) + 1; }")
+                    .unwrap();
+
+                assert_eq!(
+                    rlox.call("depth", &[Value::from(1500.0)]).unwrap(),
+                    Value::Number(1500.0)
+                );
+
+                let error = runtime_error(rlox.eval("depth(100000);").unwrap_err());
+                assert_eq!(error.kind, RuntimeErrorKind::StackOverflow);
+            }
+        })
+        .unwrap();
+
+    handle.join().unwrap();
+}
+
+#[test]
+fn natives_need_capabilities() {
+    let root = env::temp_dir().join(format!("rlox-capabilities-{}", process::id()));
+    let sandbox = root.join("sandbox");
+    fs::create_dir_all(&sandbox).unwrap();
+    fs::write(root.join("secret.txt"), "secret").unwrap();
+
+    for backend in BACKENDS {
+        let mut rlox = Rlox::with_backend(backend);
+        rlox.set_global("dir", Value::from(sandbox.to_str().unwrap()));
+
+        let error = runtime_error(
+            rlox.eval("write_file(dir + \"/a.txt\", \"hi\");")
+                .unwrap_err(),
+        );
+        assert_eq!(
+            error.kind,
+            RuntimeErrorKind::PermissionDenied {
+                capability: Capability::FsWrite
+            }
+        );
+        assert_eq!(
+            error.message(),
+            "Permission denied: writing files is not allowed."
+        );
+        assert!(rlox.eval("getenv(\"PATH\");").is_err());
+        assert!(rlox.eval("system(\"true\");").is_err());
+
+        rlox.set_capabilities(Capabilities {
+            fs_read: Some(sandbox.clone()),
+            fs_write: Some(sandbox.clone()),
+            ..Capabilities::default()
+        });
+
+        rlox.run("write_file(dir + \"/a.txt\", \"hi\");").unwrap();
+        assert_eq!(
+            rlox.eval("read_file(dir + \"/a.txt\");").unwrap(),
+            Value::from("hi")
+        );
+
+        let error = runtime_error(
+            rlox.eval("read_file(dir + \"/../secret.txt\");")
+                .unwrap_err(),
+        );
+        assert_eq!(
+            error.kind,
+            RuntimeErrorKind::PermissionDenied {
+                capability: Capability::FsRead
+            }
+        );
+
+        let error = runtime_error(rlox.eval("read_file(dir + \"/missing.txt\");").unwrap_err());
+        assert!(error.message().starts_with("Could not read"));
+    }
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn dangling_links_cannot_escape_the_write_root() {
+    let root = env::temp_dir().join(format!("rlox-dangling-{}", process::id()));
+    let sandbox = root.join("sandbox");
+    fs::create_dir_all(&sandbox).unwrap();
+    std::os::unix::fs::symlink(root.join("outside.txt"), sandbox.join("link.txt")).unwrap();
+
+    for backend in BACKENDS {
+        let mut rlox = Rlox::with_backend(backend);
+        rlox.set_global("dir", Value::from(sandbox.to_str().unwrap()));
+        rlox.set_capabilities(Capabilities {
+            fs_write: Some(sandbox.clone()),
+            ..Capabilities::default()
+        });
+
+        let error = runtime_error(
+            rlox.eval("write_file(dir + \"/link.txt\", \"escaped\");")
+                .unwrap_err(),
+        );
+        assert_eq!(
+            error.kind,
+            RuntimeErrorKind::PermissionDenied {
+                capability: Capability::FsWrite
+            }
+        );
+        assert!(!root.join("outside.txt").exists());
+    }
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+fn backtrace(error: &RuntimeError) -> Vec<String> {
+    error.backtrace.iter().map(ToString::to_string).collect()
+}
+
+#[test]
+fn runtime_errors_carry_a_backtrace() {
+    for backend in BACKENDS {
+        let mut rlox = Rlox::with_backend(backend);
+        rlox.register_fn("half", |x: f64| x / 2.0);
+
+        let source = "fn inner(x) {
+                        return half(x);
+                      }
+                      class Wrapper {
+                        wrap(x) { return inner(x); }
+                      }
+                      fn outer(x) { return Wrapper().wrap(x); }
+                      outer(4);
+                      outer(\"four\");";
+        let error = runtime_error(rlox.eval(source).unwrap_err());
+        assert_eq!(
+            backtrace(&error),
+            [
+                "[line 2] in inner()",
+                "[line 5] in wrap()",
+                "[line 7] in outer()",
+                "[line 9] in script",
+            ]
+        );
+        assert_eq!(error.backtrace[0].span, error.span());
+
+        let error = runtime_error(rlox.eval("\n-nil;").unwrap_err());
+        assert_eq!(backtrace(&error), ["[line 2] in script"]);
+
+        // Calls from the host have no script frame.
+        let error = rlox.call("inner", &[Value::Nil]).unwrap_err();
+        assert_eq!(backtrace(&error), ["[line 2] in inner()"]);
+
+        rlox.set_source_name("lib.rlox");
+        let error = rlox.call("inner", &[Value::Nil]).unwrap_err();
+        assert_eq!(error.backtrace[0].file.as_deref(), Some("lib.rlox"));
+        assert_eq!(backtrace(&error), ["[lib.rlox:2] in inner()"]);
+    }
+}
+
+#[test]
+fn compiled_modules_name_their_source_in_backtraces() {
+    let mut rlox = Rlox::with_backend(Backend::Vm);
+    let module = rlox
+        .compile("fn fail() { return -nil; }\nfail();", "app.rlox")
+        .unwrap();
+    let module = Module::from_bytes(&module.to_bytes()).unwrap();
+
+    let error = runtime_error(rlox.run_compiled(&module).unwrap_err());
+    assert_eq!(
+        backtrace(&error),
+        ["[app.rlox:1] in fail()", "[app.rlox:2] in script"]
+    );
+}
+
+#[test]
+fn gc_stats_follow_compiled_code_onto_the_vm() {
+    let mut rlox = Rlox::with_backend(Backend::TreeWalker);
+    rlox.set_gc_config(GcConfig {
+        stress: true,
+        ..GcConfig::default()
+    });
+    let module = rlox
+        .compile(
+            "class Node {} for (var i = 0; i < 10; i = i + 1) Node();",
+            "app.rlox",
+        )
+        .unwrap();
+
+    rlox.run_compiled(&module).unwrap();
+    assert!(rlox.gc_stats().collections > 0);
+}
+
+#[test]
+fn long_backtraces_are_shortened_when_reported() {
+    for backend in BACKENDS {
+        let diagnostics = Buffer::default();
+        let mut rlox = Rlox::new(backend, io::sink(), diagnostics.clone());
+        rlox.set_source_name("deep.rlox");
+        let source =
+            "fn down(n) {\n  if (n == 0) return -nil;\n  return down(n - 1);\n}\ndown(40);";
+
+        let errors = rlox.run(source).unwrap_err();
+        rlox.report_errors(&errors, source, "deep.rlox").unwrap();
+        assert_eq!(runtime_error(errors).backtrace.len(), 42);
+
+        let report = diagnostics.contents();
+        let frames: Vec<&str> = report
+            .lines()
+            .skip_while(|line| !line.starts_with('['))
+            .collect();
+        assert_eq!(frames.len(), 21);
+        assert_eq!(frames[0], "[deep.rlox:2] in down()");
+        assert_eq!(frames[1], "[deep.rlox:3] in down()");
+        assert_eq!(frames[10], "... 22 more frames");
+        assert_eq!(frames[20], "[deep.rlox:5] in script");
+    }
+}
@@ -0,0 +1,303 @@
+//! Golden-file tests in the style of the Crafting Interpreters suite.
+//!
+//! Every `.rlox` file under `tests/lox` is run through the `rlox` binary on
+//! both backends, and its output is checked against annotations in the file:
+//!
+//! - `// expect: <output>` for each line the script prints, in order;
+//! - `// expect runtime error: <message>` for the runtime error that ends it,
+//!   reported on the annotated line;
+//! - `// Error: <message>` or `// [line N] Error: <message>` for each static
+//!   error, reported on the annotated line or on line `N`.
+//!
+//! Every `.rlox` file under `tests/disasm` is compiled with `rlox disasm`, and
+//! the listing is compared with the `.disasm` file next to it.
+
+use std::{
+    env,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    process::{self, Command},
+};
+
+const BACKENDS: [&str; 2] = ["tree-walker", "vm"];
+
+const EXIT_DATA_ERROR: i32 = 65;
+const EXIT_SOFTWARE: i32 = 70;
+
+/// What follows `// ` in each kind of annotation.
+const MARKERS: [&str; 4] = ["expect: ", "expect runtime error: ", "Error: ", "[line "];
+
+/// What a script should do when run.
+#[derive(Default)]
+struct Expectations {
+    output: Vec<String>,
+    /// Static errors as `(line, message)`, in order.
+    errors: Vec<(usize, String)>,
+    runtime_error: Option<(usize, String)>,
+}
+
+impl Expectations {
+    fn parse(source: &str) -> Self {
+        let mut expectations = Expectations::default();
+
+        for (index, text) in source.lines().enumerate() {
+            let line = index + 1;
+            // Strings in the code may contain `// ` too, so look for the
+            // first one that starts an annotation.
+            let Some(comment) = text
+                .match_indices("// ")
+                .map(|(start, _)| &text[start + 3..])
+                .find(|comment| MARKERS.iter().any(|marker| comment.starts_with(marker)))
+            else {
+                continue;
+            };
+
+            if let Some(output) = comment.strip_prefix("expect: ") {
+                expectations.output.push(output.to_string());
+            } else if let Some(message) = comment.strip_prefix("expect runtime error: ") {
+                expectations.runtime_error = Some((line, message.to_string()));
+            } else if let Some(message) = comment.strip_prefix("Error: ") {
+                expectations.errors.push((line, message.to_string()));
+            } else if let Some(rest) = comment.strip_prefix("[line ")
+                && let Some((number, message)) = rest.split_once("] Error: ")
+            {
+                let line = number.parse().expect("line number in error annotation");
+                expectations.errors.push((line, message.to_string()));
+            }
+        }
+
+        expectations
+    }
+
+    fn exit_code(&self) -> i32 {
+        if !self.errors.is_empty() {
+            EXIT_DATA_ERROR
+        } else if self.runtime_error.is_some() {
+            EXIT_SOFTWARE
+        } else {
+            0
+        }
+    }
+}
+
+/// Pulls the message and line out of a JSON diagnostic printed by
+/// `--format=json`.
+fn parse_diagnostic(json: &str) -> Option<(usize, String)> {
+    let (_, rest) = json.split_once("\"message\":\"")?;
+    let mut message = String::new();
+    let mut chars = rest.chars();
+
+    loop {
+        match chars.next()? {
+            '"' => break,
+            '\\' => match chars.next()? {
+                'n' => message.push('\n'),
+                't' => message.push('\t'),
+                'r' => message.push('\r'),
+                'u' => {
+                    let code: String = chars.by_ref().take(4).collect();
+                    message.push(char::from_u32(u32::from_str_radix(&code, 16).ok()?)?);
+                }
+                c => message.push(c),
+            },
+            c => message.push(c),
+        }
+    }
+
+    let (_, rest) = json.split_once("\"line\":")?;
+    let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+
+    Some((digits.parse().ok()?, message))
+}
+
+/// Runs `path` on `backend` and describes every way the result differs from
+/// `expected`.
+fn check(path: &Path, backend: &str, expected: &Expectations) -> Vec<String> {
+    let result = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .arg("--format=json")
+        .arg(format!("--backend={}", backend))
+        .arg(path)
+        .output()
+        .expect("failed to run rlox");
+
+    let stdout = String::from_utf8_lossy(&result.stdout);
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    let mut failures = vec![];
+
+    let output: Vec<&str> = stdout.lines().collect();
+    for (index, expected_line) in expected.output.iter().enumerate() {
+        match output.get(index) {
+            Some(line) if line == expected_line => {}
+            Some(line) => failures.push(format!(
+                "output line {}: expected {:?}, got {:?}",
+                index + 1,
+                expected_line,
+                line
+            )),
+            None => failures.push(format!("missing output: {:?}", expected_line)),
+        }
+    }
+    for line in output.iter().skip(expected.output.len()) {
+        failures.push(format!("unexpected output: {:?}", line));
+    }
+
+    let mut diagnostics = vec![];
+    for line in stderr.lines() {
+        match parse_diagnostic(line) {
+            Some(diagnostic) => diagnostics.push(diagnostic),
+            None => failures.push(format!("unexpected stderr: {}", line)),
+        }
+    }
+
+    let expected_diagnostics: Vec<&(usize, String)> = expected
+        .errors
+        .iter()
+        .chain(&expected.runtime_error)
+        .collect();
+    for (index, (line, message)) in expected_diagnostics.iter().enumerate() {
+        match diagnostics.get(index) {
+            Some((actual_line, actual_message))
+                if actual_line == line && actual_message == message => {}
+            Some((actual_line, actual_message)) => failures.push(format!(
+                "expected error {:?} on line {}, got {:?} on line {}",
+                message, line, actual_message, actual_line
+            )),
+            None => failures.push(format!("missing error {:?} on line {}", message, line)),
+        }
+    }
+    for (line, message) in diagnostics.iter().skip(expected_diagnostics.len()) {
+        failures.push(format!("unexpected error {:?} on line {}", message, line));
+    }
+
+    let code = result.status.code().unwrap_or(-1);
+    if code != expected.exit_code() {
+        failures.push(format!(
+            "expected exit code {}, got {}",
+            expected.exit_code(),
+            code
+        ));
+    }
+
+    failures
+}
+
+/// Collects the files under `directory` with the given extension, sorted.
+fn scripts(directory: &Path, extension: &str, found: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = fs::read_dir(directory)
+        .expect("readable test directory")
+        .map(|entry| entry.expect("readable directory entry").path())
+        .collect();
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            scripts(&path, extension, found);
+        } else if path.extension().is_some_and(|name| name == extension) {
+            found.push(path);
+        }
+    }
+}
+
+#[test]
+fn golden_files() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox");
+    let mut paths = vec![];
+    scripts(&root, "rlox", &mut paths);
+    assert!(
+        !paths.is_empty(),
+        "no scripts found under {}",
+        root.display()
+    );
+
+    let mut report = String::new();
+    let mut failed = 0;
+
+    for path in &paths {
+        let source = fs::read_to_string(path).expect("readable test script");
+        let expected = Expectations::parse(&source);
+
+        for backend in BACKENDS {
+            let failures = check(path, backend, &expected);
+            if failures.is_empty() {
+                continue;
+            }
+
+            failed += 1;
+            let name = path.strip_prefix(&root).unwrap_or(path);
+            writeln!(report, "\n{} ({}):", name.display(), backend).unwrap();
+            for failure in failures {
+                writeln!(report, "  {}", failure).unwrap();
+            }
+        }
+    }
+
+    assert!(
+        failed == 0,
+        "{} of {} runs failed:{}",
+        failed,
+        paths.len() * BACKENDS.len(),
+        report
+    );
+}
+
+#[test]
+fn disassembly_snapshots() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/disasm");
+    let mut paths = vec![];
+    scripts(&root, "rlox", &mut paths);
+    assert!(
+        !paths.is_empty(),
+        "no scripts found under {}",
+        root.display()
+    );
+
+    for path in &paths {
+        let expected = fs::read_to_string(path.with_extension("disasm"))
+            .expect("readable disassembly snapshot");
+        let result = Command::new(env!("CARGO_BIN_EXE_rlox"))
+            .arg("disasm")
+            .arg(path)
+            .output()
+            .expect("failed to run rlox");
+
+        assert!(
+            result.status.success(),
+            "disasm failed for {}",
+            path.display()
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&result.stdout),
+            expected,
+            "disassembly of {} changed",
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn json_diagnostics_go_to_stderr_for_every_command() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox/errors/syntax.rlox");
+    let output = env::temp_dir().join(format!("rlox-json-{}.rloxc", process::id()));
+
+    for command in ["run", "check", "disasm", "compile"] {
+        let mut rlox = Command::new(env!("CARGO_BIN_EXE_rlox"));
+        rlox.arg(command).arg("--format=json");
+        if command == "compile" {
+            rlox.arg("-o").arg(&output);
+        }
+        let result = rlox.arg(&path).output().expect("failed to run rlox");
+
+        assert_eq!(result.status.code(), Some(EXIT_DATA_ERROR), "{}", command);
+        assert!(result.stdout.is_empty(), "{} wrote to stdout", command);
+
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        assert_eq!(
+            parse_diagnostic(stderr.trim()),
+            Some((1, "Invalid expression (primary)".to_string())),
+            "{}",
+            command
+        );
+    }
+    assert!(!output.exists());
+}
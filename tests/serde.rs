@@ -0,0 +1,173 @@
+//! Tests of the serde bridge, run with `--features serde`.
+
+#![cfg(feature = "serde")]
+
+use std::collections::BTreeMap;
+
+use rlox::{Backend, Rlox, Value, token::Literal};
+use serde::{
+    Deserialize, Serialize,
+    de::value::{self, SeqDeserializer},
+};
+
+const BACKENDS: [Backend; 2] = [Backend::TreeWalker, Backend::Vm];
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Limits {
+    retries: u32,
+    delay: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+enum Mode {
+    Fast,
+    Safe,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Config {
+    name: String,
+    verbose: bool,
+    fallback: Option<String>,
+    mode: Mode,
+    limits: Limits,
+}
+
+fn config() -> Config {
+    Config {
+        name: "lox".to_string(),
+        verbose: false,
+        fallback: None,
+        mode: Mode::Fast,
+        limits: Limits {
+            retries: 3,
+            delay: 0.5,
+        },
+    }
+}
+
+#[test]
+fn structs_round_trip_through_scripts() {
+    for backend in BACKENDS {
+        let mut rlox = Rlox::with_backend(backend);
+        rlox.set_global("config", rlox::to_value(&config()).unwrap());
+        assert_eq!(
+            rlox.eval("config.limits;").unwrap().to_string(),
+            "Limits instance"
+        );
+
+        let value = rlox
+            .eval(
+                "config.limits.retries = config.limits.retries + 1;
+                 config.mode = \"Safe\";
+                 config.fallback = config.name + \"-old\";
+                 config;",
+            )
+            .unwrap();
+
+        let config: Config = rlox::from_value(value).unwrap();
+        assert_eq!(config.limits.retries, 4);
+        assert_eq!(config.mode, Mode::Safe);
+        assert_eq!(config.fallback.as_deref(), Some("lox-old"));
+    }
+}
+
+#[test]
+fn maps_become_instances() {
+    let mut rlox = Rlox::with_backend(Backend::Vm);
+    let scores = BTreeMap::from([("ada", 3.0), ("bob", 4.0)]);
+    rlox.set_global("scores", rlox::to_value(&scores).unwrap());
+
+    assert_eq!(
+        rlox.eval("scores.ada + scores.bob;").unwrap(),
+        Value::Number(7.0)
+    );
+    assert_eq!(rlox.eval("scores;").unwrap().to_string(), "Object instance");
+
+    let back: BTreeMap<String, f64> = rlox::from_value(rlox.get_global("scores").unwrap()).unwrap();
+    assert_eq!(back.len(), 2);
+}
+
+#[test]
+fn instances_round_trip_without_their_class() {
+    for backend in BACKENDS {
+        let mut rlox = Rlox::with_backend(backend);
+        let point = rlox
+            .eval("class Point {} var p = Point(); p.x = 1; p.y = 2; p;")
+            .unwrap();
+
+        let back: Value = rlox::from_value(point).unwrap();
+        assert_eq!(back.to_string(), "Object instance");
+
+        rlox.set_global("back", back);
+        assert_eq!(rlox.eval("back.x + back.y;").unwrap(), Value::Number(3.0));
+    }
+}
+
+#[test]
+fn unsupported_values_are_errors() {
+    let no_lists = "Lox has no lists, so sequences can't be converted.";
+    assert_eq!(
+        rlox::to_value(&vec![1, 2]).unwrap_err().to_string(),
+        no_lists
+    );
+    assert_eq!(rlox::to_value(&(1, 2)).unwrap_err().to_string(), no_lists);
+    assert_eq!(
+        rlox::from_value::<Vec<u32>>(Value::Nil)
+            .unwrap_err()
+            .to_string(),
+        no_lists
+    );
+    let sequence = SeqDeserializer::<_, value::Error>::new([1, 2].into_iter());
+    assert_eq!(
+        Value::deserialize(sequence).unwrap_err().to_string(),
+        no_lists
+    );
+
+    assert!(rlox::from_value::<u32>(Value::Number(1.5)).is_err());
+    assert!(rlox::from_value::<u32>(Value::from("1")).is_err());
+    assert_eq!(rlox::from_value::<i32>(Value::Number(-2.0)), Ok(-2));
+
+    let mut rlox = Rlox::with_backend(Backend::TreeWalker);
+    let function = rlox.eval("fn f() {} f;").unwrap();
+    assert!(rlox::from_value::<String>(function).is_err());
+}
+
+#[test]
+fn cyclic_instances_are_errors() {
+    for backend in BACKENDS {
+        let mut rlox = Rlox::with_backend(backend);
+        let cyclic = rlox.eval("class N {} var n = N(); n.me = n; n;").unwrap();
+
+        let error = rlox::to_value(&cyclic).unwrap_err();
+        assert_eq!(error.to_string(), "cyclic instance");
+        let error = rlox::from_value::<Value>(cyclic).unwrap_err();
+        assert_eq!(error.to_string(), "cyclic instance");
+
+        let indirect = rlox
+            .eval("var a = N(); var b = N(); a.b = b; b.a = a; a;")
+            .unwrap();
+        assert!(rlox::to_value(&indirect).is_err());
+        assert!(rlox::from_value::<Value>(indirect).is_err());
+
+        // Sharing an instance without a cycle is fine.
+        let shared = rlox
+            .eval("var leaf = N(); leaf.x = 1; var root = N(); root.l = leaf; root.r = leaf; root;")
+            .unwrap();
+        assert!(rlox::to_value(&shared).is_ok());
+        let back: BTreeMap<String, BTreeMap<String, f64>> = rlox::from_value(shared).unwrap();
+        assert_eq!(back["l"], back["r"]);
+    }
+}
+
+#[test]
+fn literals_serialize_as_plain_values() {
+    let literal: Literal = rlox::from_value(Value::from("text")).unwrap();
+    assert!(matches!(literal, Literal::String(ref text) if text == "text"));
+
+    assert_eq!(
+        rlox::to_value(&Literal::Number(2.0)).unwrap(),
+        Value::Number(2.0)
+    );
+    assert_eq!(rlox::to_value(&Literal::Nil).unwrap(), Value::Nil);
+}